@@ -0,0 +1,41 @@
+//! Compare the tree walker of Exec with the compiled Program, run with `cargo bench`
+//!
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use landau::exec::{Bindings, Exec};
+use latex_analyzer::lex::Lex;
+
+const POINTS: usize = 100_000;
+
+fn time<F: FnMut(f64) -> f64>(mut f: F) -> Duration {
+    let start = Instant::now();
+    let mut total = 0.0;
+    for i in 0..POINTS {
+        total += f(i as f64 / POINTS as f64);
+    }
+    black_box(total);
+    start.elapsed()
+}
+
+fn main() {
+    let source = r"\frac{a x^2 + b x + c}{\sqrt{x^2 + 1}} - \ln{x + 2}\var{a=1.5}\var{b=-2}\var{c=0.5}";
+    let exec = Exec::from_lex(Lex::new(source.to_string())).unwrap();
+    let program = exec.compile().unwrap();
+    let x = program.slot("x").unwrap();
+    let mut values = program.values(&Bindings::new().set("x", 0.0).unwrap()).unwrap();
+
+    // names are read when bindings are made, so make them before timing
+    let bindings: Vec<Bindings> = (0..POINTS)
+        .map(|i| Bindings::new().set("x", i as f64 / POINTS as f64).unwrap())
+        .collect();
+    let mut next = bindings.iter();
+    let tree = time(|_| exec.calculate_with(next.next().unwrap()).unwrap());
+    let compiled = time(|v| {
+        values[x] = v;
+        program.run(&values).unwrap()
+    });
+
+    println!("{POINTS} points of {source}");
+    println!("tree walker: {tree:?}");
+    println!("compiled:    {compiled:?} ({:.1}x faster)", tree.as_secs_f64() / compiled.as_secs_f64());
+}
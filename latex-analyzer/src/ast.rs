@@ -1,93 +1,321 @@
 use std::rc::Rc;
-use crate::lex::{Proto, Token};
+use crate::error::ParseError;
+use crate::lex::{Arg, Lex, Proto, Token};
+use crate::parser::Parser;
+use crate::span::Span;
 
-#[derive(PartialEq, Debug)]
+// n "\pm" expand to 2^n trees, so 10 of them give 1024 trees already
+pub const MAX_PLUS_MINUS: usize = 10;
+
+#[derive(PartialEq, Debug, Clone)]
 pub enum NodeKind {
     Num,
     Op,
+    // operator with only one operand, which is stored in right, such as -x
+    Unary,
+    // left = right, an equation can only be the root of AST
+    Equation,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Node {
     pub node_kind: NodeKind,
     pub value: Option<Token>,
     pub op: Option<Token>,
     pub left: Option<Rc<Node>>,
     pub right: Option<Rc<Node>>,
+    // For function nodes, every argument is parsed as an AST as well,
+    // so \sqrt{\frac{1}{2}} has a frac node in its required_args
+    pub optional_args: Vec<Node>,
+    pub required_args: Vec<Node>,
+    // where the node comes from, an operator node covers both of its operands
+    pub span: Span,
 }
 
-pub struct AST(pub Node, pub Vec<String>);
+#[derive(Debug)]
+pub struct AST(pub Node, pub Vec<Arg>);
 
 impl AST {
-    pub fn new(proto: Proto) -> Self {
-        let (node, var) = Node::parse_rpn(proto);
-        AST(node, var)
+    pub fn new(proto: Proto) -> Result<Self, ParseError> {
+        Parser::from_proto(proto).parse()
     }
 }
 
 impl Node {
-    fn new_value_node(value: Token) -> Result<Node, String> {
-        match value {
-            Token::Expression(_) | Token::Function(_, _, _) => {
-                let node = Node {
-                    node_kind: NodeKind::Num,
-                    value: Some(value),
-                    op: None,
-                    left: None,
-                    right: None,
-                };
-
-                Ok(node)
+    /// The variables defined within arguments of a function are pushed into var
+    pub(crate) fn new_value_node(value: Token, span: Span, var: &mut Vec<Arg>) -> Result<Node, ParseError> {
+        let (optional_args, required_args) = match value {
+            Token::Expression(_) | Token::Unit(_) => (vec![], vec![]),
+            Token::Function(_, ref op, ref re) => {
+                (Node::parse_args(op, var)?, Node::parse_args(re, var)?)
             }
-            _ => Err(format!("Token {value:?} can not be value of AST!")),
+            _ => return Err(ParseError::UnexpectedToken(value, span)),
+        };
+
+        let node = Node {
+            node_kind: NodeKind::Num,
+            value: Some(value),
+            op: None,
+            left: None,
+            right: None,
+            optional_args,
+            required_args,
+            span,
+        };
+
+        Ok(node)
+    }
+
+    pub(crate) fn parse_args(args: &[Arg], var: &mut Vec<Arg>) -> Result<Vec<Node>, ParseError> {
+        let mut nodes = Vec::new();
+
+        for arg in args.iter() {
+            let mut lex = Lex::from_arg(arg);
+            let AST(node, mut v) = Parser::from_lex(&mut lex)?.parse()?;
+            nodes.push(node);
+            var.append(&mut v);
         }
+
+        Ok(nodes)
     }
 
-    fn new_op_node(op: Token, left: Node, right: Node) -> Result<Node, String> {
+    pub(crate) fn new_op_node(op: Token, span: Span, left: Node, right: Node) -> Result<Node, ParseError> {
+        // "a = b = c" or "(a = b) + c" does not make sense
+        if let Some(equation) = [&left, &right].iter().find(|n| n.node_kind == NodeKind::Equation) {
+            return Err(ParseError::UnexpectedToken(Token::Equal, equation.equal_span()));
+        }
+
         match op {
-            Token::Add | Token::Sub | Token::Div | Token::Times | Token::Superscript(_) => {
+            Token::Add | Token::Sub | Token::PlusMinus | Token::Div | Token::Times
+            | Token::Superscript(_) | Token::Equal => {
+                let node_kind = if op == Token::Equal { NodeKind::Equation } else { NodeKind::Op };
                 let node = Node {
-                    node_kind: NodeKind::Op,
+                    node_kind,
                     value: None,
                     op: Some(op),
+                    span: left.span.merge(right.span),
                     left: Some(Rc::new(left)),
                     right: Some(Rc::new(right)),
+                    optional_args: vec![],
+                    required_args: vec![],
                 };
 
                 Ok(node)
             }
-            _ => Err(format!("Token {op:?} can not be a operator!")),
+            _ => Err(ParseError::UnexpectedToken(op, span)),
         }
     }
 
-    fn parse_rpn(expr: Proto) -> (Node, Vec<String>) {
-        let mut stack = Vec::new();
-        let mut var = Vec::new();
+    pub(crate) fn new_unary_node(op: Token, span: Span, operand: Node) -> Result<Node, ParseError> {
+        if operand.node_kind == NodeKind::Equation {
+            return Err(ParseError::UnexpectedToken(Token::Equal, operand.equal_span()));
+        }
 
-        for e in expr.into_iter() {
-            match e {
-                Token::Expression(_) | Token::Function(_, _, _) => {
-                    stack.push(Node::new_value_node(e).unwrap());
-                }
-                Token::Superscript(content) => {
-                    let op2 = Node::new_value_node(Token::Expression(content)).unwrap();
-                    let op1 = stack.pop().unwrap();
+        if !matches!(op, Token::Add | Token::Sub | Token::PlusMinus) {
+            return Err(ParseError::UnexpectedToken(op, span));
+        }
 
-                    // the content of superscript will no longer be used
-                    stack.push(Node::new_op_node(
-                        Token::Superscript(String::new()), op1, op2).unwrap())
-                }
-                Token::Var(s) => var.push(s),
-                Token::Eos => break,
-                _ => {
-                    let op2 = stack.pop().unwrap();
-                    let op1 = stack.pop().unwrap();
+        Ok(Node {
+            node_kind: NodeKind::Unary,
+            value: None,
+            op: Some(op),
+            left: None,
+            span: span.merge(operand.span),
+            right: Some(Rc::new(operand)),
+            optional_args: vec![],
+            required_args: vec![],
+        })
+    }
+
+    /// Replace every "\pm" with "+" and "-", so a tree with n "\pm" becomes 2^n trees without "\pm".
+    /// The trees taking "+" come first, so "a \pm b" gives ["a + b", "a - b"].
+    /// A tree with more than [MAX_PLUS_MINUS] "\pm" is an error
+    pub fn expand_plus_minus(&self) -> Result<Vec<Node>, ParseError> {
+        let count = self.count_plus_minus();
+        if count > MAX_PLUS_MINUS {
+            return Err(ParseError::TooManyPlusMinus(count, MAX_PLUS_MINUS, self.span));
+        }
+
+        Ok(self.expand())
+    }
+
+    fn count_plus_minus(&self) -> usize {
+        let children = self.left.iter().chain(self.right.iter()).map(|c| c.as_ref());
+        let own = usize::from(self.op == Some(Token::PlusMinus));
+        children.chain(self.optional_args.iter()).chain(self.required_args.iter())
+            .map(|child| child.count_plus_minus())
+            .sum::<usize>() + own
+    }
+
+    fn expand(&self) -> Vec<Node> {
+        let expand_all = |nodes: &[Node]| -> Vec<Vec<Node>> {
+            nodes.iter().fold(vec![vec![]], |acc, node| {
+                let expanded = node.expand();
+                acc.iter()
+                    .flat_map(|prefix| expanded.iter().map(move |n| {
+                        let mut v = prefix.clone();
+                        v.push(n.clone());
+                        v
+                    }))
+                    .collect()
+            })
+        };
+        let expand_child = |child: &Option<Rc<Node>>| match child {
+            Some(c) => c.expand().into_iter().map(|n| Some(Rc::new(n))).collect(),
+            None => vec![None],
+        };
+
+        let mut nodes = Vec::new();
+        for optional_args in expand_all(&self.optional_args) {
+            for required_args in expand_all(&self.required_args) {
+                nodes.push(Node { optional_args: optional_args.clone(), required_args, ..self.clone() });
+            }
+        }
+
+        let lefts = expand_child(&self.left);
+        let rights = expand_child(&self.right);
+        let ops = match self.op {
+            Some(Token::PlusMinus) => vec![Some(Token::Add), Some(Token::Sub)],
+            ref op => vec![op.clone()],
+        };
 
-                    stack.push(Node::new_op_node(e, op1, op2).unwrap());
+        let mut result = Vec::new();
+        for node in nodes.iter() {
+            for op in ops.iter() {
+                for left in lefts.iter() {
+                    for right in rights.iter() {
+                        result.push(Node { op: op.clone(), left: left.clone(), right: right.clone(), ..node.clone() });
+                    }
                 }
             }
         }
 
-        (stack.pop().unwrap(), var)
+        result
+    }
+
+    /// Names of identifiers in the tree, each only once and in the order they appear,
+    /// such as ["a", "\\pi", "r"] for "a + \\pi r^2 + a"
+    pub fn variables(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        self.collect_variables(&mut names);
+
+        names
+    }
+
+    fn collect_variables(&self, names: &mut Vec<String>) {
+        if let Some(Token::Expression(e)) = &self.value {
+            if !e.starts_with(|c: char| c.is_ascii_digit()) && !names.contains(e) {
+                names.push(e.clone());
+            }
+        }
+        let children = self.left.iter().chain(self.right.iter()).map(|c| c.as_ref());
+        for child in children.chain(self.optional_args.iter()).chain(self.required_args.iter()) {
+            child.collect_variables(names);
+        }
+    }
+
+    /// The span between two sides of an equation, where the "=" is
+    fn equal_span(&self) -> Span {
+        let left = self.left.as_ref().map_or(self.span.start, |l| l.span.end);
+        let right = self.right.as_ref().map_or(self.span.end, |r| r.span.start);
+        Span::new(left, right)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{NodeKind, AST, MAX_PLUS_MINUS};
+    use crate::error::ParseError;
+    use crate::lex::{Arg, Lex, Token};
+    use crate::span::Span;
+
+    fn ast(s: &str) -> Result<AST, ParseError> {
+        let mut lex = Lex::new(s.to_string());
+        AST::new(lex.parse()?)
+    }
+
+    #[test]
+    fn new_test() {
+        let ast = ast(r"a + \frac{1}{2}\var{a=1}").unwrap();
+
+        assert_eq!(ast.0.span, Span::new(0, 15));
+        assert_eq!(ast.1[0].value, "a=1");
+    }
+
+    #[test]
+    fn new_error_test() {
+        assert_eq!(ast("a +").unwrap_err(), ParseError::MissingOperand(Span::new(2, 3)));
+        assert_eq!(ast("").unwrap_err(), ParseError::MissingOperand(Span::new(0, 0)));
+        assert_eq!(ast("a + * b").unwrap_err(), ParseError::MissingOperand(Span::new(2, 3)));
+        assert_eq!(ast("a -").unwrap_err(), ParseError::MissingOperand(Span::new(2, 3)));
+    }
+
+    #[test]
+    fn args_test() {
+        let tree = ast(r"\sqrt[3]{\frac{a+1}{b\var{b=2}}}").unwrap();
+        let sqrt = &tree.0;
+        let frac = &sqrt.required_args[0];
+
+        assert_eq!(sqrt.optional_args[0].value, Some(Token::Expression("3".to_string())));
+        assert_eq!(frac.value, Some(Token::Function(
+            "frac".to_string(),
+            vec![],
+            vec![Arg::new("a+1".to_string(), Span::new(15, 18)), Arg::new(r"b\var{b=2}".to_string(), Span::new(20, 30))],
+        )));
+        assert_eq!(frac.required_args[0].node_kind, NodeKind::Op);
+        assert_eq!(frac.required_args[0].span, Span::new(15, 18));
+        assert_eq!(tree.1[0].value, "b=2");
+        assert_eq!(
+            ast(r"\frac{a+}{b}").unwrap_err(),
+            ParseError::MissingOperand(Span::new(7, 8)),
+        );
+    }
+
+    #[test]
+    fn equation_test() {
+        let tree = ast("ax^2 + bx + c = 0").unwrap();
+
+        assert_eq!(tree.0.node_kind, NodeKind::Equation);
+        assert_eq!(tree.0.op, Some(Token::Equal));
+        assert_eq!(tree.0.right.as_ref().unwrap().value, Some(Token::Expression("0".to_string())));
+        assert_eq!(ast("a = b = c").unwrap_err(), ParseError::UnexpectedToken(Token::Equal, Span::new(1, 4)));
+        assert_eq!(ast("(a = b) + c").unwrap_err(), ParseError::UnexpectedToken(Token::Equal, Span::new(2, 5)));
+    }
+
+    #[test]
+    fn unary_test() {
+        let ast = ast("-x^2").unwrap();
+
+        assert_eq!(ast.0.node_kind, NodeKind::Unary);
+        assert_eq!(ast.0.op, Some(Token::Sub));
+        assert_eq!(ast.0.span, Span::new(0, 4));
+        assert_eq!(ast.0.right.as_ref().unwrap().node_kind, NodeKind::Op);
+    }
+
+    #[test]
+    fn expand_plus_minus_test() {
+        let tree = ast(r"\sqrt{a \pm b} \pm c").unwrap().0;
+        let trees = tree.expand_plus_minus().unwrap();
+        let ops: Vec<(Option<Token>, Option<Token>)> = trees.iter()
+            .map(|t| (t.op.clone(), t.left.as_ref().unwrap().required_args[0].op.clone()))
+            .collect();
+
+        assert_eq!(ops, vec![
+            (Some(Token::Add), Some(Token::Add)),
+            (Some(Token::Add), Some(Token::Sub)),
+            (Some(Token::Sub), Some(Token::Add)),
+            (Some(Token::Sub), Some(Token::Sub)),
+        ]);
+        assert_eq!(ast("a + b").unwrap().0.expand_plus_minus().unwrap().len(), 1);
+
+        let many = |n: usize| ast(&vec!["a"; n + 1].join(r" \pm ")).unwrap().0.expand_plus_minus();
+        assert_eq!(many(MAX_PLUS_MINUS).unwrap().len(), 1 << MAX_PLUS_MINUS);
+        assert_eq!(many(MAX_PLUS_MINUS + 1).unwrap_err(), ParseError::TooManyPlusMinus(11, 10, Span::new(0, 67)));
+    }
+
+    #[test]
+    fn variables_test() {
+        let tree = ast(r"a + \pi r^2 + \sqrt[n]{a b_1} + 2").unwrap().0;
+        assert_eq!(tree.variables(), vec!["a", "\\pi", "r", "n", "b_1"]);
     }
 }
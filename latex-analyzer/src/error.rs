@@ -0,0 +1,61 @@
+//! [crate::error] describes what can go wrong when LaTeX is turned into an AST
+//!
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use crate::lex::Token;
+use crate::span::Span;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum ParseError {
+    // a char which lex can not understand, such as '?'
+    UnknownChar(char, Span),
+    // a '{' or '[' without its closing one, or a '}' without its opening one
+    UnbalancedBrace(Span),
+    // a '(' without ')' or a ')' without '('
+    UnbalancedParen(Span),
+    // an operator which does not have enough operands, such as "a +"
+    MissingOperand(Span),
+    // two operands without any operator between them
+    MissingOperator(Span),
+    // a huge symbol without its subscript and superscript, such as \sum{i}
+    MissingLimits(String, Span),
+    // a token which can not appear at its position
+    UnexpectedToken(Token, Span),
+    // more "\pm" than an expression may expand, how many it has and the most it may have
+    TooManyPlusMinus(usize, usize, Span),
+}
+
+impl ParseError {
+    /// Where the error occurs
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnknownChar(_, span)
+            | ParseError::UnbalancedBrace(span)
+            | ParseError::UnbalancedParen(span)
+            | ParseError::MissingOperand(span)
+            | ParseError::MissingOperator(span)
+            | ParseError::MissingLimits(_, span)
+            | ParseError::UnexpectedToken(_, span)
+            | ParseError::TooManyPlusMinus(_, _, span) => *span,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnknownChar(ch, _) => write!(f, "I can`t read char: {ch}"),
+            ParseError::UnbalancedBrace(_) => write!(f, "Brace is not closed"),
+            ParseError::UnbalancedParen(_) => write!(f, "Parenthesis is not closed"),
+            ParseError::MissingOperand(_) => write!(f, "Missing operand"),
+            ParseError::MissingOperator(_) => write!(f, "Missing operator"),
+            ParseError::MissingLimits(fun, _) => write!(f, "Function {fun} miss args!"),
+            ParseError::UnexpectedToken(token, _) => write!(f, "Token {token:?} should not occurred here!"),
+            ParseError::TooManyPlusMinus(found, max, _) => {
+                write!(f, "There are {found} \\pm, but at most {max} can be expanded")
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
@@ -1,28 +1,76 @@
 //! [crate::lex] used to converts LaTeX expressions into Vec<Token> (also called Proto)
 //!
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Read};
 use lazy_static::lazy_static;
+use crate::error::ParseError;
+use crate::span::{Span, Spanned};
 
 lazy_static! {
     // we call \int, \sum and \prod as huge symbol
-    static ref HUGE_SYMBOL: Vec<String> = {
+    pub(crate) static ref HUGE_SYMBOL: Vec<String> = {
         vec!["int".to_string(), "sum".to_string(), "prod".to_string()]
     };
 
+    // functions which can take their argument without braces, such as \sin x or \log_2 x
+    pub(crate) static ref PREFIX_FUNCTION: Vec<String> = {
+        ["sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan",
+            "sinh", "cosh", "tanh", "coth", "arsinh", "arcosh", "artanh", "ln", "log", "exp"]
+            .iter().map(|s| s.to_string()).collect()
+    };
+
     // some symbols are used for decoration, such as \left and \right
     static ref IGNORE_SYMBOL: Vec<String> = {
-        vec!["left".to_string(), "right".to_string()]
+        vec!["left".to_string(), "right".to_string(), "quad".to_string(), "qquad".to_string()]
+    };
+
+    // operators which are written as control sequences, such as \cdot
+    static ref OPERATOR_SYMBOL: HashMap<String, Token> = {
+        HashMap::from([
+            ("cdot".to_string(), Token::Times),
+            ("times".to_string(), Token::Times),
+            ("div".to_string(), Token::Div),
+            ("pm".to_string(), Token::PlusMinus),
+        ])
     };
+
+    // symbols of units and whether they take prefixes, only the names of the units in landau`s unit table
+    pub static ref UNIT_SYMBOL: Vec<(&'static str, bool)> = vec![
+        ("g", true), ("m", true), ("s", true), ("A", true), ("K", true), ("mol", true), ("cd", true),
+        ("Hz", true), ("N", true), ("Pa", true), ("J", true), ("W", true), ("C", true), ("V", true),
+        ("\\Omega", true), ("F", true), ("S", true), ("T", true), ("Wb", true), ("H", true), ("L", true),
+        ("eV", true), ("min", false), ("h", false), ("d", false), ("t", false), ("bar", false),
+    ];
+
+    pub static ref UNIT_PREFIX: Vec<&'static str> = vec![
+        "Y", "Z", "E", "P", "T", "G", "M", "k", "h", "da", "d", "c", "m", "µ", "u", "n", "p", "f", "a",
+    ];
+}
+
+/// Whether the content of \mathrm is made of known units, such as "km/h" or "kg \cdot m^2".
+/// It only checks the names, so a unit which is written wrong otherwise is an error when it is calculated
+pub fn is_unit(content: &str) -> bool {
+    let known = |symbol: &str| UNIT_SYMBOL.iter().any(|(u, _)| *u == symbol) || UNIT_PREFIX.iter().any(|prefix| {
+        symbol.strip_prefix(prefix).is_some_and(|rest| UNIT_SYMBOL.contains(&(rest, true)))
+    });
+
+    let normalized = content.replace(r"\cdot", " ").replace(r"\,", " ").replace(r"\mu", "µ");
+    let mut symbols = normalized.split(|c: char| !(c.is_alphabetic() || c == '\\'))
+        .filter(|s| !s.is_empty())
+        .peekable();
+    symbols.peek().is_some() && symbols.all(known)
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Token {
-    // Expression only contains letters, numbers and decimal
+    // Expression is either a number, such as "1.5", or a single letter, such as "x".
+    // As LaTeX does, "ax" stands for a times x rather than a variable named "ax"
     Expression(String),
     // Function`s name, optional arguments and required arguments
     // For example, \sqrt[3]{2} will be parsed as 'Function("sqrt", ["3"], ["2"])'
-    Function(String, Vec<String>, Vec<String>),
+    // Every argument remembers where its content is, so we can point into it later
+    Function(String, Vec<Arg>, Vec<Arg>),
     // symbol "="
     Equal,
     // symbol "+"
@@ -33,6 +81,8 @@ pub enum Token {
     Sub,
     // symbol "*"
     Times,
+    // symbol "\pm", a \pm b has two results: a + b and a - b
+    PlusMinus,
     // The expression within the parentheses and brackets should be represented as ParL(String) as well, in my opinion.
     // However, the nested expression would be too complex to parse, so just leave this task to exec :)
     // (
@@ -43,31 +93,44 @@ pub enum Token {
     SquareL,
     // ]
     SquareR,
-    // we need } to divide blocks
+    // Braces which are not arguments of a function are used for grouping, just like parentheses
+    // {
+    BraceL,
+    // }
     BraceR,
     // The string representing superscripts and subscripts follows the syntax of a carat (^) for superscripts and
     // an underscore (_) for subscripts.
     // For superscripts, "^2" should be translated to Superscript("2"), and "^{2}" should be handled in the same manner too.
     // For subscripts, "_{22}" is converted to Subscript("22").
+    // "^" is right-associative, so "^3^2" is Superscript("3^2") and the content is parsed as an expression later
+    // A subscript right after an identifier is a part of its name, see [Lex::post_process]
     // ^
-    Superscript(String),
+    Superscript(Arg),
     // _
-    Subscript(String),
+    Subscript(Arg),
     // .
     Dot,
     // ,
     Comma,
     // See [Landau LaTeX standard] for explanation
-    Var(String),
+    Var(Arg),
+    // the content of \mathrm, such as "m/s" in 3\,\mathrm{m/s}, which is a unit rather than an expression.
+    // \mathrm of anything else, such as \mathrm{Re}, is an identifier named "\mathrm{Re}"
+    Unit(String),
     // \n or \0
     Eos,
 }
 
-pub type Proto = Vec<Token>;
+/// The raw content of an argument, such as the "1" in \frac{1}{2}
+pub type Arg = Spanned<String>;
+
+pub type Proto = Vec<Spanned<Token>>;
 
 pub struct Lex {
     cursor: usize,
     input: Vec<char>,
+    // where the input starts in the whole LaTeX expression, see [Lex::from_arg]
+    offset: usize,
 }
 
 impl Lex {
@@ -75,7 +138,22 @@ impl Lex {
         let mut v: Vec<char> = input.chars().collect();
         v.push('\0');
 
-        Lex { cursor: 0, input: v }
+        Lex { cursor: 0, input: v, offset: 0 }
+    }
+
+    /// Lex the content of an argument, such as the "a+1" in \frac{a+1}{b}.
+    /// The spans of tokens still point into the whole expression that the argument belongs to
+    pub fn from_arg(arg: &Arg) -> Self {
+        let mut lex = Lex::new(arg.value.clone());
+        lex.offset = arg.span.start;
+
+        lex
+    }
+
+    /// The input of lex, which is what the spans of tokens point into
+    pub fn source(&self) -> String {
+        // the last char is the '\0' pushed by [Lex::new]
+        self.input[..self.input.len() - 1].iter().collect()
     }
 
     pub fn from_file(mut input: File) -> Self {
@@ -85,74 +163,136 @@ impl Lex {
         Lex::new(s)
     }
 
-    pub fn parse(&mut self) -> Proto {
+    pub fn parse(&mut self) -> Result<Proto, ParseError> {
         let mut vec = Vec::new();
         loop {
-            match self.next() {
+            let t = self.next_spanned()?;
+            match t.value {
                 Token::Eos => {
                     break;
                 }
-                t => match t {
-                    // In LaTeX expression, we often use empty braces {} to distinct blocks enhancing readability.
-                    // For example, we opt for a^2{}b over a^2b to represent the multiplication of a^2 and b.
-                    // The empty braces {} will be parsed as 'Expression("")', which is meaningless for exec,
-                    // so we will filter it out
-                    Token::Expression(e) if e.is_empty() => (),
-                    t => vec.push(t),
-                }
+                // Ignored symbols, such as \left, will be parsed as 'Expression("")',
+                // which is meaningless for exec, so we will filter it out
+                Token::Expression(ref e) if e.is_empty() => (),
+                _ => vec.push(t),
             }
         }
 
-        Lex::post_process(vec).unwrap_or_else(|e| panic!("{e}"))
+        Lex::post_process(vec)
     }
 
     /// Some optimizations on parsed proto.
     /// As expected, there should be just 'Expression', 'Function' 'Add', 'Div', 'Sub', 'Times',
     /// 'ParL' and 'ParR' in the proto
-    fn post_process(proto: Proto) -> Result<Proto, String> {
-        let mut proto = proto.into_iter();
+    fn post_process(proto: Proto) -> Result<Proto, ParseError> {
+        let mut proto = proto.into_iter().peekable();
         let mut vec = Vec::new();
         let mut var_stack = Vec::new();
 
-        loop {
-            let po = proto.next();
-            if po.is_none() {
-                break;
-            }
-            let po = po.unwrap();
-            match po {
+        let mut end = 0;
+
+        while let Some(po) = proto.next() {
+            end = po.span.end;
+            match po.value {
                 // Convert subscripts and superscripts of huge symbols into optional arguments.
                 // Caution: For huge symbols, optional_arguments[0] stands for subscript and [1] for superscript
                 Token::Function(fun, _, _) if HUGE_SYMBOL.contains(&fun) => {
                     let (sub, sup) = match (proto.next(), proto.next()) {
                         // fun_a^b -> Function("fun", ["a", "b"], [])
-                        (Some(Token::Subscript(sub)), Some(Token::Superscript(sup))) =>
-                            (sub, sup),
+                        (Some(Spanned { value: Token::Subscript(sub), .. }),
+                            Some(Spanned { value: Token::Superscript(sup), span })) => {
+                            end = span.end;
+                            (sub, sup)
+                        }
                         // fun^a_b -> Function("fun", ["b", "a"], [])
-                        (Some(Token::Superscript(sup)), Some(Token::Subscript(sub))) =>
-                            (sub, sup),
-                        _ => return Err(format!("function {fun} miss args!")),
+                        (Some(Spanned { value: Token::Superscript(sup), .. }),
+                            Some(Spanned { value: Token::Subscript(sub), span })) => {
+                            end = span.end;
+                            (sub, sup)
+                        }
+                        _ => return Err(ParseError::MissingLimits(fun, po.span)),
+                    };
+                    let span = Span::new(po.span.start, end);
+                    vec.push(Spanned::new(Token::Function(fun, vec![sub, sup], vec![]), span))
+                }
+                // The subscript of a function without arguments is its base, such as \log_2 x -> Function("log", ["2"], []).
+                // The superscript is left for the parser, since \sin^2 x is the square of \sin x
+                Token::Function(fun, op, re) if PREFIX_FUNCTION.contains(&fun) && op.is_empty() && re.is_empty() => {
+                    let sup = match proto.peek() {
+                        Some(Spanned { value: Token::Superscript(_), .. }) => proto.next(),
+                        _ => None,
                     };
-                    vec.push(Token::Function(fun, vec![sub, sup], vec![]))
+                    match proto.next_if(|p| matches!(p.value, Token::Subscript(_))) {
+                        Some(Spanned { value: Token::Subscript(sub), span }) => {
+                            end = span.end;
+                            vec.push(Spanned::new(Token::Function(fun, vec![sub], vec![]), po.span.merge(span)));
+                        }
+                        _ => vec.push(Spanned::new(Token::Function(fun, op, re), po.span)),
+                    }
+                    vec.extend(sup);
+                }
+                // Subscripted symbols, such as T_0 or k_{max}, are variables with their own names.
+                // x^2_i means the same as x_i^2, so the superscript is moved behind the subscript
+                Token::Expression(name) if Lex::is_identifier(&name) => {
+                    let sup = match proto.peek() {
+                        Some(Spanned { value: Token::Superscript(_), .. }) => proto.next(),
+                        _ => None,
+                    };
+                    match proto.next_if(|p| matches!(p.value, Token::Subscript(_))) {
+                        Some(Spanned { value: Token::Subscript(sub), span }) => {
+                            end = span.end;
+                            let name = Lex::subscripted_name(&name, &sub.value);
+                            vec.push(Spanned::new(Token::Expression(name), po.span.merge(span)));
+                        }
+                        _ => vec.push(Spanned::new(Token::Expression(name), po.span)),
+                    }
+                    vec.extend(sup);
                 }
                 Token::Var(_) => var_stack.push(po),
-                t => {
-                    vec.push(t);
+                _ => {
+                    vec.push(po);
                 }
             }
         }
-        vec.extend(var_stack.into_iter());
-        vec.push(Token::Eos);
+        vec.extend(var_stack);
+        vec.push(Spanned::new(Token::Eos, Span::new(end, end)));
 
         Ok(vec)
     }
 
+    /// Read next token with the span it occupies in the input
+    fn next_spanned(&mut self) -> Result<Spanned<Token>, ParseError> {
+        while self.input[self.cursor] == ' ' {
+            self.cursor += 1;
+        }
+
+        let start = self.cursor;
+        let token = self.next()?;
+        Ok(Spanned::new(token, self.span(start, self.cursor)))
+    }
+
+    /// An identifier is a letter, such as "x", or a symbol, such as "\mu"
+    fn is_identifier(name: &str) -> bool {
+        name.starts_with(|ch: char| ch.is_alphabetic() || ch == '\\')
+    }
+
+    /// The name of a subscripted identifier, "T" with subscript "0" is "T_0",
+    /// and "k" with subscript "max" is "k_{max}", just as how we write them in LaTeX
+    fn subscripted_name(name: &str, sub: &str) -> String {
+        let sub = sub.replace(' ', "");
+        if sub.chars().count() == 1 {
+            format!("{name}_{sub}")
+        } else {
+            format!("{name}_{{{sub}}}")
+        }
+    }
+
     // Read next token
-    fn next(&mut self) -> Token {
+    fn next(&mut self) -> Result<Token, ParseError> {
         let ch = self.read_char();
 
-        match ch {
-            ' ' => self.next(),
+        let token = match ch {
+            ' ' => return self.next(),
             '\0' | '\n' => Token::Eos,
 
             '=' => Token::Equal,
@@ -167,34 +307,50 @@ impl Lex {
             ',' => Token::Comma,
             '.' => Token::Dot,
 
-            '_' => Token::Subscript(self.read_subscript()),
-            '^' => Token::Superscript(self.read_subscript()),
-            'a'..='z' | 'A'..='Z' => {
-                self.put_back();  // to read a full string
-                Token::Expression(self.read_pure_string())
-            }
+            '_' => Token::Subscript(self.read_subscript()?),
+            '^' => Token::Superscript(self.read_superscript()?),
+            'a'..='z' | 'A'..='Z' => Token::Expression(ch.to_string()),
             '0'..='9' => {
                 self.put_back();
                 Token::Expression(self.read_pure_number())
             }
-            '{' => Token::Expression(self.read_until_brace_r()),
+            '{' => Token::BraceL,
             '}' => Token::BraceR,
             '\\' => {
-                let t = self.read_function();
+                let t = self.read_function()?;
                 match t {
-                    Token::Function(fun, op, mut re) => {
-                        return if fun == "var" {
-                            Token::Var(re.remove(0))
+                    Token::Function(fun, _, mut re) if fun == "var" => {
+                        if re.is_empty() {
+                            return Err(ParseError::MissingOperand(self.last_char_span()));
+                        }
+                        Token::Var(re.remove(0))
+                    }
+                    Token::Function(fun, _, mut re) if fun == "mathrm" => {
+                        if re.is_empty() {
+                            return Err(ParseError::MissingOperand(self.last_char_span()));
+                        }
+                        let content = re.remove(0).value;
+                        if is_unit(&content) {
+                            Token::Unit(content)
                         } else {
-                            Token::Function(fun, op, re)
+                            Token::Expression(format!("\\mathrm{{{content}}}"))
                         }
                     }
+                    // A control sequence without any argument, such as \mu or \pi, is a symbol
+                    // rather than a function, so it is treated as an identifier named "\mu".
+                    // Huge symbols get their arguments later in [Lex::post_process]
+                    Token::Function(fun, op, re)
+                    if op.is_empty() && re.is_empty() && !HUGE_SYMBOL.contains(&fun) && !PREFIX_FUNCTION.contains(&fun) => {
+                        Token::Expression(format!("\\{fun}"))
+                    }
                     _ => t  // maybe Expression
                 }
             }
 
-            _ => panic!("I can`t read char: {ch}"),
-        }
+            _ => return Err(ParseError::UnknownChar(ch, self.last_char_span())),
+        };
+
+        Ok(token)
     }
 
     /// Note: This function does not verify whether the number of arguments provided
@@ -208,34 +364,48 @@ impl Lex {
     /// of this crate.
     /// Moreover, it is not our duty to validate if the LaTeX input has anything wrong.
     /// If incorrect LaTeX is provided, we can throw an error in exec :)
-    fn read_function(&mut self) -> Token {
-        let name: String;
+    fn read_function(&mut self) -> Result<Token, ParseError> {
         let mut optional_args = Vec::new();
         let mut required_args = Vec::new();
 
-        name = self.read_pure_string();
+        // the name of a control sequence only contains letters
+        let name = self.read_pure_string();
+        if name.is_empty() {
+            return match self.read_char() {
+                // spaces, such as the "\," in 3\,\mathrm{m}
+                ',' | ';' | ':' | '!' | ' ' => Ok(Token::Expression(String::new())),
+                ch => Err(ParseError::UnknownChar(ch, self.last_char_span())),
+            };
+        }
         if IGNORE_SYMBOL.contains(&name) {
             // an 'Expression' with empty content will be ignored by [parse()]
-            return Token::Expression(String::new());
+            return Ok(Token::Expression(String::new()));
+        }
+        if let Some(op) = OPERATOR_SYMBOL.get(&name) {
+            // an operator takes no argument, so "\cdot{b}" is "\cdot b" rather than a function
+            return Ok(op.clone());
         }
 
         loop {
             match self.read_char() {
                 '[' => {
-                    match self.read_pure_string() {
-                        s if s.is_empty() => (),
-                        s => optional_args.push(s),
+                    let open = self.last_char_span();
+                    let start = self.cursor;
+                    let s = self.read_until_square_r();
+                    let span = self.span(start, self.cursor);
+                    if self.read_char() != ']' {
+                        return Err(ParseError::UnbalancedBrace(open));
+                    }
+                    if !s.is_empty() {
+                        optional_args.push(Arg::new(s, span));
                     }
                 }
                 '{' => {
-                    match self.read_until_brace_r() {
-                        s if s.is_empty() => (),
-                        s => required_args.push(s),
+                    match self.read_group()? {
+                        a if a.value.is_empty() => (),
+                        a => required_args.push(a),
                     }
                 }
-                ']' | '}' => {
-                    continue;
-                }
                 _ => {
                     self.put_back();
                     break;
@@ -243,7 +413,7 @@ impl Lex {
             }
         }
 
-        Token::Function(name, optional_args, required_args)
+        Ok(Token::Function(name, optional_args, required_args))
     }
 
     /// Read string with a specific condition
@@ -275,11 +445,13 @@ impl Lex {
     /// corresponding '}' that pairs with the initial '{'.
     /// If the input string does not start with a '{', the function will read until it encounters
     /// a standalone '}'.
+    /// Everything between the braces is kept as it is, it is not our business to check it here.
     fn read_until_brace_r(&mut self) -> String {
         let mut count = 0;
 
         let mut s = self.read_string(|ch| {
             match ch {
+                '\0' => false,
                 '{' => {
                     count += 1;
                     true
@@ -292,37 +464,126 @@ impl Lex {
                         false
                     }
                 }
-                _ => {
-                    ch.is_alphanumeric() || ch == '_' || ch == '^' || ch == '.'
-                        || ch == '+' || ch == '-' || ch == '*' || ch == '/'
-                        || ch == ' ' || ch == '\\' || ch == '='
-                }
+                _ => true,
             }
         });
 
-        if s.len() > 1 && s.starts_with('{') && s.ends_with('}') {
+        // "{a}+{b}" starts with '{' and ends with '}' as well, but it should not be unwrapped,
+        // so make sure the first '{' is not closed until the last char
+        let wrapped = s.len() > 1 && s.starts_with('{') && s.ends_with('}') && {
+            let mut depth = 0;
+            s.chars().take(s.chars().count() - 1)
+                .all(|ch| {
+                    match ch {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => (),
+                    }
+                    depth > 0
+                })
+        };
+        if wrapped {
             s.remove(0);  // remove the first char, which is a '{'
             s.pop();  // remove the last char, which is a '}'
         }
-        return s;
+        s
+    }
+
+    /// Read a group whose '{' has just been read, the closing '}' is read as well
+    fn read_group(&mut self) -> Result<Arg, ParseError> {
+        let open = self.last_char_span();
+        let arg = self.read_arg();
+
+        match self.read_char() {
+            '}' => Ok(arg),
+            _ => Err(ParseError::UnbalancedBrace(open)),
+        }
+    }
+
+    /// Read the content of an optional argument until the ']' which closes it,
+    /// a ']' within braces, such as the first ']' in \sqrt[{\sqrt[3]{2}}]{x}, does not count
+    fn read_until_square_r(&mut self) -> String {
+        let mut count = 0;
+
+        self.read_string(|ch| {
+            match ch {
+                '\0' => false,
+                ']' => count > 0,
+                '{' => {
+                    count += 1;
+                    true
+                }
+                '}' => {
+                    count -= 1;
+                    true
+                }
+                _ => true,
+            }
+        })
     }
 
-    /// Contains letters, numbers and decimal
+    /// Same as [Lex::read_until_brace_r], but also records where the content is
+    fn read_arg(&mut self) -> Arg {
+        let start = self.cursor;
+        let s = self.read_until_brace_r();
+        let len = s.chars().count();
+        // the outer braces have been removed if the length changed
+        let start = if self.cursor - start == len { start } else { start + 1 };
+
+        Arg::new(s, self.span(start, start + len))
+    }
+
+    /// Contains letters only, such as the name of a control sequence
     fn read_pure_string(&mut self) -> String {
-        self.read_string(|ch| ch.is_alphanumeric() || ch == '.')
+        self.read_string(|ch| ch.is_alphabetic())
     }
 
     /// The content of '_{abc}' is 'abc', the content of '_abc' is 'a'
-    fn read_subscript(&mut self) -> String {
+    fn read_subscript(&mut self) -> Result<Arg, ParseError> {
         match self.read_char() {
-            '{' => {
-                self.put_back();
-                self.read_until_brace_r()
+            ' ' => self.read_subscript(),
+            '{' => self.read_group(),
+            '\0' | '\n' | '}' => Err(ParseError::MissingOperand(self.last_char_span())),
+            // '_\alpha' takes the whole control sequence
+            '\\' => {
+                let start = self.cursor - 1;
+                let name = self.read_pure_string();
+                Ok(Arg::new(format!("\\{name}"), self.span(start, self.cursor)))
             }
-            c => String::from(c)
+            c => Ok(Arg::new(String::from(c), self.last_char_span())),
         }
     }
 
+    /// Same as [Lex::read_subscript], but "^" is right-associative, so "a^b^c" means "a^{b^c}".
+    /// Therefore, the superscripts which follow are a part of the content, "^b^c" is Superscript("b^c")
+    fn read_superscript(&mut self) -> Result<Arg, ParseError> {
+        while self.read_char() == ' ' {}
+        self.put_back();
+        let start = self.cursor;
+        let arg = self.read_subscript()?;
+
+        let end = self.cursor;
+        while self.read_char() == ' ' {}
+        if self.input[self.cursor - 1] != '^' {
+            self.cursor = end;
+            return Ok(arg);
+        }
+
+        self.read_superscript()?;
+        let content = self.input[start..self.cursor].iter().collect();
+        Ok(Arg::new(content, self.span(start, self.cursor)))
+    }
+
+    /// The span of the char which has just been read
+    fn last_char_span(&self) -> Span {
+        self.span(self.cursor - 1, self.cursor)
+    }
+
+    /// The span from start to end of the input
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span::new(start, end).shift(self.offset)
+    }
+
     fn put_back(&mut self) {
         self.cursor -= 1;
     }
@@ -336,7 +597,13 @@ impl Lex {
 #[cfg(test)]
 mod tests {
     use std::fs::File;
-    use crate::lex::{Lex, Token};
+    use crate::error::ParseError;
+    use crate::lex::{is_unit, Arg, Lex, Token};
+    use crate::span::Span;
+
+    fn arg(s: &str, start: usize, end: usize) -> Arg {
+        Arg::new(s.to_string(), Span::new(start, end))
+    }
 
     #[test]
     fn read_char_test() {
@@ -375,38 +642,187 @@ mod tests {
         let mut l = Lex::from_file(f);
         l.read_char();
 
-        assert_eq!(l.read_function(), Token::Function(
+        assert_eq!(l.read_function().unwrap(), Token::Function(
             "frac".to_string(),
             vec![],
-            vec!["1".to_string(),
-                 "2".to_string()],
+            vec![arg("1", 6, 7),
+                 arg("2", 9, 10)],
         ));
 
         l.read_char();
         l.read_char();
 
-        assert_eq!(l.read_function(), Token::Function(
+        assert_eq!(l.read_function().unwrap(), Token::Function(
             "sqrt".to_string(),
-            vec!["2".to_string()],
-            vec!["1".to_string()],
+            vec![arg("2", 18, 19)],
+            vec![arg("1", 21, 22)],
         ));
 
         l.read_char();
         l.read_char();
 
-        assert_eq!(l.read_function(), Token::Function(
+        assert_eq!(l.read_function().unwrap(), Token::Function(
             "rho".to_string(),
             vec![],
             vec![],
         ));
     }
 
+    #[test]
+    fn span_test() {
+        let mut l = Lex::new(r"a +  \frac{b}{c}".to_string());
+        let v = l.parse().unwrap();
+
+        assert_eq!(v[0].span, Span::new(0, 1));
+        assert_eq!(v[1].span, Span::new(2, 3));
+        assert_eq!(v[2].span, Span::new(5, 16));
+        assert_eq!(v[2].value, Token::Function(
+            "frac".to_string(),
+            vec![],
+            vec![arg("b", 11, 12), arg("c", 14, 15)],
+        ));
+    }
+
+    #[test]
+    fn parse_letters_test() {
+        let mut l = Lex::new(r"2ax{}b".to_string());
+        let v: Vec<Token> = l.parse().unwrap().into_iter().map(|t| t.value).collect();
+
+        assert_eq!(v, vec![
+            Token::Expression("2".to_string()),
+            Token::Expression("a".to_string()),
+            Token::Expression("x".to_string()),
+            Token::BraceL,
+            Token::BraceR,
+            Token::Expression("b".to_string()),
+            Token::Eos,
+        ]);
+    }
+
+    #[test]
+    fn from_arg_test() {
+        let mut l = Lex::new(r"\sqrt[n+1]{\frac{a}{b}}".to_string());
+        let Token::Function(_, op, re) = l.parse().unwrap().remove(0).value else {
+            panic!("sqrt should be parsed as a function");
+        };
+
+        assert_eq!(op, vec![arg("n+1", 6, 9)]);
+        let mut l = Lex::from_arg(&re[0]);
+        let v = l.parse().unwrap();
+        assert_eq!(v[0].span, Span::new(11, 22));
+        assert_eq!(v[0].value, Token::Function(
+            "frac".to_string(),
+            vec![],
+            vec![arg("a", 17, 18), arg("b", 20, 21)],
+        ));
+    }
+
+    #[test]
+    fn parse_symbol_test() {
+        let mut l = Lex::new(r"\mu{}c + 2\pi r + \Delta x".to_string());
+        let v: Vec<Token> = l.parse().unwrap().into_iter().map(|t| t.value).collect();
+        let e = |s: &str| Token::Expression(s.to_string());
+
+        assert_eq!(v, vec![
+            e(r"\mu"), e("c"), Token::Add, e("2"), e(r"\pi"), e("r"), Token::Add, e(r"\Delta"), e("x"), Token::Eos,
+        ]);
+        assert_eq!(
+            Lex::new(r"a\?".to_string()).parse().unwrap_err(),
+            ParseError::UnknownChar('?', Span::new(2, 3)),
+        );
+    }
+
+    #[test]
+    fn parse_unit_test() {
+        let mut l = Lex::new(r"3\,\mathrm{m/s} + v \quad".to_string());
+        let v: Vec<Token> = l.parse().unwrap().into_iter().map(|t| t.value).collect();
+        let e = |s: &str| Token::Expression(s.to_string());
+
+        assert_eq!(v, vec![e("3"), Token::Unit("m/s".to_string()), Token::Add, e("v"), Token::Eos]);
+        assert!(Lex::new(r"3\mathrm".to_string()).parse().is_err());
+
+        let mut l = Lex::new(r"\mathrm{Re} + \mathrm{kg \cdot m^2/s^2} + \mathrm{d}x + \mathrm{mm}".to_string());
+        let v: Vec<Token> = l.parse().unwrap().into_iter().map(|t| t.value).collect();
+        let u = |s: &str| Token::Unit(s.to_string());
+        assert_eq!(v, vec![
+            e(r"\mathrm{Re}"), Token::Add, u(r"kg \cdot m^2/s^2"), Token::Add, u("d"), e("x"), Token::Add, u("mm"), Token::Eos,
+        ]);
+        assert!(!is_unit("kmin") && !is_unit("") && !is_unit("furlong"));
+    }
+
+    #[test]
+    fn parse_operator_test() {
+        let mut l = Lex::new(r"a \cdot b \times c \div{d} \pm e".to_string());
+        let v: Vec<Token> = l.parse().unwrap().into_iter().map(|t| t.value).collect();
+        let e = |s: &str| Token::Expression(s.to_string());
+
+        assert_eq!(v, vec![
+            e("a"), Token::Times, e("b"), Token::Times, e("c"), Token::Div,
+            Token::BraceL, e("d"), Token::BraceR, Token::PlusMinus, e("e"), Token::Eos,
+        ]);
+    }
+
+    #[test]
+    fn read_superscript_test() {
+        let sup = |s: &str| {
+            let mut l = Lex::new(s.to_string());
+            l.read_char();
+            l.read_superscript()
+        };
+
+        assert_eq!(sup("^2x").unwrap(), arg("2", 1, 2));
+        assert_eq!(sup("^{x+1}").unwrap(), arg("x+1", 2, 5));
+        assert_eq!(sup("^3^2").unwrap(), arg("3^2", 1, 4));
+        assert_eq!(sup("^ {3} ^{2} x").unwrap(), arg("{3} ^{2}", 2, 10));
+        assert_eq!(sup(r"^\alpha").unwrap(), arg(r"\alpha", 1, 7));
+        assert_eq!(sup("^").unwrap_err(), ParseError::MissingOperand(Span::new(1, 2)));
+    }
+
+    #[test]
+    fn parse_subscript_test() {
+        let mut l = Lex::new(r"T_0 + k_{max} + \rho_{ 0 } + x^2_i + \int_a^b".to_string());
+        let v = l.parse().unwrap();
+        let e = |s: &str| Token::Expression(s.to_string());
+
+        assert_eq!(v[0].value, e("T_0"));
+        assert_eq!(v[0].span, Span::new(0, 3));
+        assert_eq!(v[2].value, e("k_{max}"));
+        assert_eq!(v[4].value, e(r"\rho_0"));
+        assert_eq!(v[6].value, e("x_i"));
+        assert_eq!(v[7].value, Token::Superscript(arg("2", 31, 32)));
+        assert_eq!(v[9].value, Token::Function(
+            "int".to_string(),
+            vec![arg("a", 42, 43), arg("b", 44, 45)],
+            vec![],
+        ));
+    }
+
+    #[test]
+    fn read_until_brace_r_nested_test() {
+        let mut l = Lex::new("{a}+{b}".to_string());
+        let mut l1 = Lex::new("{{a}+{b}}".to_string());
+
+        assert_eq!(l.read_until_brace_r(), "{a}+{b}".to_string());
+        assert_eq!(l1.read_until_brace_r(), "{a}+{b}".to_string());
+    }
+
+    #[test]
+    fn parse_error_test() {
+        let err = |s: &str| Lex::new(s.to_string()).parse().unwrap_err();
+
+        assert_eq!(err("a + ?"), ParseError::UnknownChar('?', Span::new(4, 5)));
+        assert_eq!(err(r"\frac{1}{2"), ParseError::UnbalancedBrace(Span::new(8, 9)));
+        assert_eq!(err(r"\sqrt[3{2}"), ParseError::UnbalancedBrace(Span::new(5, 6)));
+        assert_eq!(err("a^"), ParseError::MissingOperand(Span::new(2, 3)));
+        assert_eq!(err(r"\sum{i}"), ParseError::MissingLimits("sum".to_string(), Span::new(0, 7)));
+    }
+
     #[test]
     fn parse_test1() {
         let test = r"\frac{k}{k_0} = \left(\frac{T}{T_0}\right)^{1.5}\left(\frac{T_0 + T_s}{T + T_{s}}\right)
                 ".to_string();
         let mut l = Lex::new(test);
-        let v = l.parse();
+        let v = l.parse().unwrap();
 
         for i in v {
             println!("{:?}", i);
@@ -417,7 +833,7 @@ mod tests {
     fn parse_test2() {
         let test = r"Pr = \frac{\mu{}c_p}{k}".to_string();
         let mut l = Lex::new(test);
-        let v = l.parse();
+        let v = l.parse().unwrap();
 
         for i in v {
             println!("{:?}", i);
@@ -428,7 +844,7 @@ mod tests {
     fn parse_test3() {
         let test = r"\vec{u}_A(x + \Delta{}x, y + \Delta{}y, z + \Delta{}z, t)".to_string();
         let mut l = Lex::new(test);
-        let v = l.parse();
+        let v = l.parse().unwrap();
 
         for i in v {
             println!("{:?}", i);
@@ -439,7 +855,7 @@ mod tests {
     fn parse_test4() {
         let test = r"\int_a^b{x}\di{x}".to_string();
         let mut l = Lex::new(test);
-        let v = l.parse();
+        let v = l.parse().unwrap();
 
         for i in v {
             println!("{:?}", i);
@@ -451,7 +867,7 @@ mod tests {
     fn parse_test5() {
         let test = r"\frac{1}{2} + \sqrt[3]{4}".to_string();
         let mut l = Lex::new(test);
-        let v = l.parse();
+        let v = l.parse().unwrap();
 
         for i in v {
             println!("{:?}", i);
@@ -462,7 +878,7 @@ mod tests {
     fn parse_test6() {
         let test = r"\left(a + \frac{b}{c}\right) + d".to_string();
         let mut l = Lex::new(test);
-        let v = l.parse();
+        let v = l.parse().unwrap();
 
         for i in v {
             println!("{:?}", i);
@@ -473,7 +889,7 @@ mod tests {
     fn parse_test7() {
         let test = r"a+1\var{a=1}".to_string();
         let mut l = Lex::new(test);
-        let v = l.parse();
+        let v = l.parse().unwrap();
 
         for i in v {
             println!("{:?}", i);
@@ -1,6 +1,9 @@
 pub mod lex;
 pub mod parser;
 pub mod ast;
+pub mod span;
+pub mod error;
+pub mod printer;
 
 #[cfg(test)]
 mod tests {
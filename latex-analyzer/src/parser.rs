@@ -1,95 +1,502 @@
-use crate::lex::{Lex, Proto, Token};
+//! [crate::parser] turns the proto from [crate::lex::Lex] into an [AST] by precedence climbing (Pratt parsing).
+//! Every operator declares how tightly it binds its operands, see [Parser::infix_binding_power],
+//! [Parser::prefix_binding_power] and [Parser::postfix_binding_power]
+//!
+use crate::ast::{Node, AST};
+use crate::error::ParseError;
+use crate::lex::{Arg, Lex, Proto, Token, HUGE_SYMBOL, PREFIX_FUNCTION};
+use crate::span::{Span, Spanned};
 
 pub struct Parser {
     proto: Proto,
+    cursor: usize,
+    // variables defined by \var, they are not a part of the expression
+    var: Vec<Arg>,
+    // whether we are reading the argument of a function without braces, which ends before the next function
+    in_argument: bool,
+    // whether we are reading the body of \int, which ends at its differential
+    in_integrand: bool,
 }
 
 impl Parser {
-    pub fn from_lex(lex: &mut Lex) -> Self {
-        Parser { proto: lex.parse() }
+    pub fn from_lex(lex: &mut Lex) -> Result<Self, ParseError> {
+        Ok(Parser::from_proto(lex.parse()?))
     }
 
     pub fn from_proto(proto: Proto) -> Self {
-        Parser { proto }
+        let mut var = Vec::new();
+        let mut proto: Proto = Parser::remove_empty_groups(proto).into_iter()
+            .filter_map(|p| match p.value {
+                Token::Var(v) => {
+                    var.push(v);
+                    None
+                }
+                _ => Some(p),
+            })
+            .collect();
+
+        // make sure the parser always stops at Eos
+        if !matches!(proto.last(), Some(Spanned { value: Token::Eos, .. })) {
+            let end = proto.last().map_or(0, |p| p.span.end);
+            proto.push(Spanned::new(Token::Eos, Span::new(end, end)));
+        }
+
+        Parser { proto, cursor: 0, var, in_argument: false, in_integrand: false }
     }
 
     /// Caution: this function will take the ownership
-    /// Make infix proto to postfix proto
-    pub fn to_postfix_proto(self) -> Proto {
-        let mut postfix = Vec::new();
-        let mut stack = Vec::new();
-        let mut var_stack = Vec::new();
-
-        for p in self.proto.into_iter() {
-            match p {
-                Token::Expression(_) | Token::Function(_, _, _) => {
-                    postfix.push(p);
+    /// Parse the whole proto into an AST
+    pub fn parse(mut self) -> Result<AST, ParseError> {
+        let node = self.parse_expr(0)?;
+
+        let next = self.next();
+        match next.value {
+            Token::Eos => Ok(AST(node, self.var)),
+            Token::ParR => Err(ParseError::UnbalancedParen(next.span)),
+            Token::BraceR => Err(ParseError::UnbalancedBrace(next.span)),
+            t => Err(ParseError::UnexpectedToken(t, next.span)),
+        }
+    }
+
+    /// Parse an expression whose operators bind tighter than min_bp
+    fn parse_expr(&mut self, min_bp: u8) -> Result<Node, ParseError> {
+        let mut left = self.parse_prefix()?;
+
+        loop {
+            // the integrand ends at its differential, such as x^2 \di{x}
+            if self.in_integrand && Parser::ends_with_differential(&left) {
+                break;
+            }
+            let op = self.peek().clone();
+
+            if let Some(bp) = Parser::postfix_binding_power(&op.value) {
+                if bp < min_bp {
+                    break;
                 }
-                Token::ParL => stack.push(p),
-                Token::ParR => {
-                    while !stack.is_empty() && *stack.last().unwrap() != Token::ParL {
-                        postfix.push(stack.pop().unwrap());
-                    }
-                    stack.pop();
+                self.next();
+                left = self.parse_postfix(op, left)?;
+                continue;
+            }
+
+            // an operand right after another one is multiplied implicitly, such as "2x" or "(a+b)(a-b)"
+            let implicit = Parser::starts_operand(&op.value);
+            // \sin x \cos x is (\sin x)(\cos x) rather than \sin(x \cos x), and \sin x \di{x} is (\sin x) \di{x}
+            if implicit && self.in_argument && (Parser::is_prefix_function(&op.value) || Parser::is_differential(&op.value)) {
+                break;
+            }
+            let op = if implicit {
+                Spanned::new(Token::Times, Span::new(op.span.start, op.span.start))
+            } else {
+                op
+            };
+
+            let Some((left_bp, right_bp)) = Parser::infix_binding_power(&op.value) else {
+                break;
+            };
+            if left_bp < min_bp {
+                break;
+            }
+            if !implicit {
+                self.next();
+            }
+
+            let right = self.parse_expr(right_bp)?;
+            left = Node::new_op_node(op.value, op.span, left, right)?;
+        }
+
+        Ok(left)
+    }
+
+    /// Parse an operand, which may start with prefix operators
+    fn parse_prefix(&mut self) -> Result<Node, ParseError> {
+        let token = self.next();
+
+        match token.value {
+            ref t if Parser::is_prefix_function(t) => self.parse_function(token),
+            ref t if Parser::is_huge_symbol(t) => self.parse_huge_symbol(token),
+            Token::Expression(_) | Token::Function(_, _, _) | Token::Unit(_) => {
+                Node::new_value_node(token.value, token.span, &mut self.var)
+            }
+            Token::ParL | Token::BraceL => self.parse_group(token),
+            op => match Parser::prefix_binding_power(&op) {
+                Some(bp) => {
+                    let operand = self.parse_expr(bp)?;
+                    Node::new_unary_node(op, token.span, operand)
                 }
-                Token::Add | Token::Sub | Token::Times | Token::Div | Token::Superscript(_) => {
-                    while !stack.is_empty()
-                        && Parser::weight(&stack.last().unwrap()) >= Parser::weight(&p)
-                    {
-                        postfix.push(stack.pop().unwrap());
+                None => match op {
+                    // point at the operator which misses its operand, such as the "+" in "a + * b"
+                    Token::Eos | Token::ParR | Token::BraceR | Token::Equal | Token::Times | Token::Div => {
+                        Err(ParseError::MissingOperand(self.previous_span(token.span)))
                     }
-                    stack.push(p);
-                }
-                Token::Var(_) => var_stack.push(p),
-                Token::Eos => break,
-                _ => panic!("Token {p:?} should not occurred here!"),
+                    t => Err(ParseError::UnexpectedToken(t, token.span)),
+                },
+            },
+        }
+    }
+
+    /// Parse a function whose argument has no braces, such as \sin 2x, \sin^2 x or \log_2(x + 1).
+    /// The argument is a product, and parentheses or braces right after the function are the whole argument,
+    /// so \sin(x) y is (\sin x) y
+    fn parse_function(&mut self, token: Spanned<Token>) -> Result<Node, ParseError> {
+        let Token::Function(name, op, _) = token.value else {
+            return Err(ParseError::UnexpectedToken(token.value, token.span));
+        };
+        let power = match self.peek().value {
+            Token::Superscript(_) => Some(self.next()),
+            _ => None,
+        };
+        // \sin^{-1} x is the inverse function, rather than the reciprocal
+        let (name, power) = match power {
+            Some(Spanned { value: Token::Superscript(ref sup), .. })
+            if sup.value.replace(' ', "") == "-1" && Parser::inverse_function(&name).is_some() => {
+                (Parser::inverse_function(&name).unwrap().to_string(), None)
             }
+            _ => (name, power),
+        };
+
+        let next = self.peek().clone();
+        if !Parser::starts_operand(&next.value) {
+            return Err(ParseError::MissingOperand(token.span));
         }
+        let in_argument = std::mem::replace(&mut self.in_argument, true);
+        let argument = match next.value {
+            Token::ParL | Token::BraceL => self.parse_prefix(),
+            _ => self.parse_expr(5),
+        };
+        self.in_argument = in_argument;
+        let argument = argument?;
 
-        while let Some(element) = stack.pop() {
-            postfix.push(element);
+        let mut node = Node::new_value_node(Token::Function(name, op, vec![]), token.span, &mut self.var)?;
+        node.span = node.span.merge(argument.span);
+        node.required_args.push(argument);
+
+        match power {
+            Some(power) => self.parse_postfix(power, node),
+            None => Ok(node),
+        }
+    }
+
+    /// Parse a huge symbol with its body, such as \prod_{i=1}^{n} (1 + \frac{1}{i}).
+    /// The body is a product, so \prod_{i=1}^{n} 2i + 1 is (\prod_{i=1}^{n} 2i) + 1,
+    /// and braces right after the limits are the whole body, just like the argument of a function.
+    /// The body of \int is the integrand with its differential, so it ends right after \di{x} or \mathrm{d}x
+    fn parse_huge_symbol(&mut self, token: Spanned<Token>) -> Result<Node, ParseError> {
+        let next = self.peek().clone();
+        if !Parser::starts_operand(&next.value) {
+            return Err(ParseError::MissingOperand(token.span));
         }
-        postfix.extend(var_stack.into_iter());
-        postfix.push(Token::Eos);
+        let is_integral = matches!(&token.value, Token::Function(name, _, _) if name == "int");
+        // \prod_{i=1}^{n} i \sin i is the product of i \sin i
+        let in_argument = std::mem::replace(&mut self.in_argument, false);
+        let in_integrand = std::mem::replace(&mut self.in_integrand, is_integral);
+        let body = match next.value {
+            // the differential may follow the braces, such as \int_a^b{x}\di{x}
+            Token::BraceL if !is_integral => self.parse_prefix(),
+            _ => self.parse_expr(5),
+        };
+        self.in_argument = in_argument;
+        self.in_integrand = in_integrand;
+        let body = body?;
 
-        postfix
+        let mut node = Node::new_value_node(token.value, token.span, &mut self.var)?;
+        node.span = node.span.merge(body.span);
+        node.required_args.push(body);
+        Ok(node)
     }
 
-    fn weight(token: &Token) -> u8 {
+    fn is_huge_symbol(token: &Token) -> bool {
+        matches!(token, Token::Function(name, _, re) if re.is_empty() && HUGE_SYMBOL.contains(name))
+    }
+
+    /// \di{x}, or the \mathrm{d} of \mathrm{d}x
+    fn is_differential(token: &Token) -> bool {
         match token {
-            Token::Add | Token::Sub => 1,
-            Token::Times | Token::Div => 2,
-            Token::Superscript(_) => 3,
-            _ => 0,
+            Token::Function(name, _, _) => name == "di",
+            Token::Unit(unit) => unit == "d",
+            _ => false,
         }
     }
+
+    /// Whether the last factor of a product is a differential, such as x \di{x} or x \mathrm{d}x
+    fn ends_with_differential(node: &Node) -> bool {
+        fn last(node: &Node) -> &Node {
+            match node.op {
+                Some(Token::Times) => node.right.as_ref().unwrap(),
+                _ => node,
+            }
+        }
+
+        match (&node.value, &node.op) {
+            (Some(Token::Function(name, _, _)), _) => name == "di",
+            (_, Some(Token::Times)) => {
+                let (left, right) = (node.left.as_ref().unwrap(), node.right.as_ref().unwrap());
+                let after_d = matches!(&last(left).value, Some(t) if Parser::is_differential(t));
+                Parser::ends_with_differential(right) || (after_d && matches!(right.value, Some(Token::Expression(_))))
+            }
+            _ => false,
+        }
+    }
+
+    fn is_prefix_function(token: &Token) -> bool {
+        matches!(token, Token::Function(name, _, re) if re.is_empty() && PREFIX_FUNCTION.contains(name))
+    }
+
+    fn inverse_function(name: &str) -> Option<&'static str> {
+        match name {
+            "sin" => Some("arcsin"),
+            "cos" => Some("arccos"),
+            "tan" => Some("arctan"),
+            "sinh" => Some("arsinh"),
+            "cosh" => Some("arcosh"),
+            "tanh" => Some("artanh"),
+            _ => None,
+        }
+    }
+
+    /// Parse the expression within parentheses or braces, whose opening token has just been read
+    fn parse_group(&mut self, open: Spanned<Token>) -> Result<Node, ParseError> {
+        // a group is an argument on its own, so \sin(x \cos x) is \sin of the whole group
+        let in_argument = std::mem::replace(&mut self.in_argument, false);
+        let in_integrand = std::mem::replace(&mut self.in_integrand, false);
+        let node = self.parse_expr(0);
+        self.in_argument = in_argument;
+        self.in_integrand = in_integrand;
+        let node = node?;
+
+        let close = self.next();
+        match (open.value, close.value) {
+            (Token::ParL, Token::ParR) | (Token::BraceL, Token::BraceR) => Ok(node),
+            (_, Token::ParR) => Err(ParseError::UnbalancedParen(close.span)),
+            (_, Token::BraceR) => Err(ParseError::UnbalancedBrace(close.span)),
+            (Token::ParL, Token::Eos) => Err(ParseError::UnbalancedParen(open.span)),
+            (_, Token::Eos) => Err(ParseError::UnbalancedBrace(open.span)),
+            (_, t) => Err(ParseError::UnexpectedToken(t, close.span)),
+        }
+    }
+
+    /// Apply a postfix operator, which has just been read, to its operand
+    fn parse_postfix(&mut self, op: Spanned<Token>, operand: Node) -> Result<Node, ParseError> {
+        let span = op.span;
+
+        match op.value {
+            Token::Superscript(content) => {
+                // the content of superscript is an expression as well, such as e^{-\frac{t}{\tau}}
+                let exponent = Node::parse_args(std::slice::from_ref(&content), &mut self.var)?.remove(0);
+
+                // the content of superscript will no longer be used
+                let op = Token::Superscript(Arg::new(String::new(), content.span));
+                Node::new_op_node(op, span, operand, exponent)
+            }
+            t => Err(ParseError::UnexpectedToken(t, span)),
+        }
+    }
+
+    /// How tightly an infix operator binds its left and right operands, the larger the tighter.
+    /// The right one is larger for left-associative operators, so "a - b - c" is "(a - b) - c"
+    fn infix_binding_power(token: &Token) -> Option<(u8, u8)> {
+        match token {
+            // both sides of an equation are calculated before "="
+            Token::Equal => Some((1, 2)),
+            Token::Add | Token::Sub | Token::PlusMinus => Some((3, 4)),
+            Token::Times | Token::Div => Some((5, 6)),
+            _ => None,
+        }
+    }
+
+    fn prefix_binding_power(token: &Token) -> Option<u8> {
+        match token {
+            // -x^2 is -(x^2), but -x * y is (-x) * y
+            Token::Add | Token::Sub | Token::PlusMinus => Some(7),
+            _ => None,
+        }
+    }
+
+    fn postfix_binding_power(token: &Token) -> Option<u8> {
+        match token {
+            // a superscript carries its exponent, so "a^b^c" is already "a^{b^c}" after lexing
+            Token::Superscript(_) => Some(9),
+            _ => None,
+        }
+    }
+
+    fn starts_operand(token: &Token) -> bool {
+        matches!(token, Token::Expression(_) | Token::Function(_, _, _) | Token::Unit(_) | Token::ParL | Token::BraceL)
+    }
+
+    fn peek(&self) -> &Spanned<Token> {
+        &self.proto[self.cursor.min(self.proto.len() - 1)]
+    }
+
+    fn next(&mut self) -> Spanned<Token> {
+        let token = self.peek().clone();
+        self.cursor += 1;
+        token
+    }
+
+    /// The span of the token before the one which has just been read
+    fn previous_span(&self, current: Span) -> Span {
+        match self.cursor.min(self.proto.len()).checked_sub(2) {
+            Some(i) => self.proto[i].span,
+            None => current,
+        }
+    }
+
+    /// In LaTeX expression, we often use empty braces {} to distinct blocks enhancing readability.
+    /// For example, we opt for a^2{}b over a^2b to represent the multiplication of a^2 and b.
+    /// The empty braces mean nothing for calculation, so we will filter them out
+    fn remove_empty_groups(proto: Proto) -> Proto {
+        let mut vec: Proto = Vec::new();
+
+        for p in proto.into_iter() {
+            match (&p.value, vec.last()) {
+                (Token::BraceR, Some(Spanned { value: Token::BraceL, .. })) => {
+                    vec.pop();
+                }
+                _ => vec.push(p),
+            }
+        }
+
+        vec
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::lex::Lex;
+    use crate::ast::{Node, NodeKind};
+    use crate::error::ParseError;
+    use crate::lex::{Arg, Lex, Token};
     use crate::parser::Parser;
+    use crate::span::Span;
 
-    #[test]
-    fn to_postfix_proto_test1() {
-        let mut lex = Lex::new(r"a + b * (c - d) / e".to_string());
-        let parser = Parser::from_lex(&mut lex);
-        let proto = parser.to_postfix_proto();
+    /// Print the tree as an S-expression, such as "(+ a (* b c))"
+    fn sexp(node: &Node) -> String {
+        let op = match &node.op {
+            Some(Token::Add) => "+",
+            Some(Token::Sub) => "-",
+            Some(Token::PlusMinus) => "pm",
+            Some(Token::Times) => "*",
+            Some(Token::Div) => "/",
+            Some(Token::Equal) => "=",
+            Some(Token::Superscript(_)) => "^",
+            _ => "",
+        };
 
-        // abcd-*e/+
-        for p in proto.iter() {
-            println!("{:?}", p);
+        match node.node_kind {
+            NodeKind::Num => match &node.value {
+                Some(Token::Expression(e)) => e.clone(),
+                Some(Token::Function(f, _, _)) => format!("\\{f}"),
+                Some(Token::Unit(u)) => format!("\\mathrm{{{u}}}"),
+                _ => String::new(),
+            },
+            NodeKind::Unary => format!("({op} {})", sexp(node.right.as_ref().unwrap())),
+            NodeKind::Op | NodeKind::Equation => {
+                format!("({op} {} {})", sexp(node.left.as_ref().unwrap()), sexp(node.right.as_ref().unwrap()))
+            }
         }
     }
 
+    fn parse(s: &str) -> Result<String, ParseError> {
+        let mut lex = Lex::new(s.to_string());
+        Ok(sexp(&Parser::from_lex(&mut lex)?.parse()?.0))
+    }
+
     #[test]
-    fn to_postfix_proto_test2() {
-        let mut lex = Lex::new(r"a + (\frac{1}{2} + 3) * \sqrt[3]{2}".to_string());
-        let parser = Parser::from_proto(lex.parse());
-        let proto = parser.to_postfix_proto();
+    fn parse_test1() {
+        assert_eq!(parse(r"a + b * (c - d) / e").unwrap(), "(+ a (/ (* b (- c d)) e))");
+        assert_eq!(parse(r"a - b - c").unwrap(), "(- (- a b) c)");
+    }
 
-        for p in proto.iter() {
-            println!("{:?}", p);
-        }
+    #[test]
+    fn parse_test2() {
+        assert_eq!(parse(r"a + (\frac{1}{2} + 3) * \sqrt[3]{2}").unwrap(), r"(+ a (* (+ \frac 3) \sqrt))");
+        assert_eq!(parse(r"a^2 b\var{b=1}").unwrap(), "(* (^ a 2) b)");
+    }
+
+    #[test]
+    fn parse_error_test() {
+        let err = |s: &str| parse(s).unwrap_err();
+
+        assert_eq!(err("(a + b"), ParseError::UnbalancedParen(Span::new(0, 1)));
+        assert_eq!(err("a + b)"), ParseError::UnbalancedParen(Span::new(5, 6)));
+        assert_eq!(err("a , b"), ParseError::UnexpectedToken(Token::Comma, Span::new(2, 3)));
+        assert_eq!(err("a}"), ParseError::UnbalancedBrace(Span::new(1, 2)));
+        assert_eq!(err("{a)"), ParseError::UnbalancedParen(Span::new(2, 3)));
+        assert_eq!(err("{a"), ParseError::UnbalancedBrace(Span::new(0, 1)));
+        assert_eq!(
+            err("(a)_b"),
+            ParseError::UnexpectedToken(Token::Subscript(Arg::new("b".to_string(), Span::new(4, 5))), Span::new(3, 5)),
+        );
+        assert_eq!(err("a + * b"), ParseError::MissingOperand(Span::new(2, 3)));
+        assert_eq!(err("()"), ParseError::MissingOperand(Span::new(0, 1)));
+    }
+
+    #[test]
+    fn implicit_times_test() {
+        assert_eq!(parse("2x").unwrap(), "(* 2 x)");
+        assert_eq!(parse("a^2{}b").unwrap(), "(* (^ a 2) b)");
+        assert_eq!(parse(r"2\pi r").unwrap(), r"(* (* 2 \pi) r)");
+        assert_eq!(parse("(a+b)(a-b)").unwrap(), "(* (+ a b) (- a b))");
+        assert_eq!(parse(r"{a}\frac{1}{2} + c").unwrap(), r"(+ (* a \frac) c)");
+        assert_eq!(parse(r"a \cdot b + c \div d").unwrap(), "(+ (* a b) (/ c d))");
+    }
+
+    #[test]
+    fn equal_test() {
+        assert_eq!(parse("ax^2 + bx + c = 0").unwrap(), "(= (+ (+ (* a (^ x 2)) (* b x)) c) 0)");
+        assert_eq!(parse("a = b = c").unwrap_err(), ParseError::UnexpectedToken(Token::Equal, Span::new(1, 4)));
+    }
+
+    #[test]
+    fn unary_test() {
+        assert_eq!(parse("-x + 3").unwrap(), "(+ (- x) 3)");
+        assert_eq!(parse("2 * (-a)").unwrap(), "(* 2 (- a))");
+        assert_eq!(parse("a - -b").unwrap(), "(- a (- b))");
+        assert_eq!(parse("+-x").unwrap(), "(+ (- x))");
+        assert_eq!(parse("-x^2").unwrap(), "(- (^ x 2))");
+        assert_eq!(parse("-x y").unwrap(), "(* (- x) y)");
+        assert_eq!(parse(r"x = \pm 2").unwrap(), "(= x (pm 2))");
+    }
+
+    #[test]
+    fn function_test() {
+        let print = |s: &str| Parser::from_lex(&mut Lex::new(s.to_string())).unwrap().parse().unwrap().0.to_latex();
+
+        assert_eq!(print(r"\sin x"), r"\sin{x}");
+        assert_eq!(print(r"\sin 2x + 1"), r"\sin{2x} + 1");
+        assert_eq!(print(r"\sin x \cos x"), r"\sin{x} \cdot \cos{x}");
+        assert_eq!(print(r"\sin(x) y"), r"\sin{x} \cdot y");
+        assert_eq!(print(r"\sin(x \cos x)"), r"\sin{x \cdot \cos{x}}");
+        assert_eq!(print(r"\sin^2 x"), r"(\sin{x})^{2}");
+        assert_eq!(print(r"\sin^{-1} x"), r"\arcsin{x}");
+        assert_eq!(print(r"\log_2 x"), r"\log_{2}{x}");
+        assert_eq!(print(r"\log_{10}(x+1)"), r"\log_{10}{x + 1}");
+        assert_eq!(print(r"\ln{x}^2"), r"(\ln{x})^{2}");
+        assert_eq!(print(r"\log_2{8} x"), r"\log_{2}{8} \cdot x");
+        assert_eq!(print(r"-\exp x^2"), r"-\exp{x^{2}}");
+        assert_eq!(parse(r"\sin + 1").unwrap_err(), ParseError::MissingOperand(Span::new(0, 4)));
+    }
+
+    #[test]
+    fn huge_symbol_test() {
+        let print = |s: &str| Parser::from_lex(&mut Lex::new(s.to_string())).unwrap().parse().unwrap().0.to_latex();
+
+        assert_eq!(parse(r"\prod_{i=1}^{n} 2i + 1").unwrap(), r"(+ \prod 1)");
+        assert_eq!(print(r"\prod_{i=1}^{n} 2i + 1"), r"\prod_{i = 1}^{n}{2i} + 1");
+        assert_eq!(print(r"\prod_{i=1}^{n} (i + 1)^2"), r"\prod_{i = 1}^{n}{(i + 1)^{2}}");
+        assert_eq!(print(r"\prod_{i=1}^{n}{i}^2"), r"(\prod_{i = 1}^{n}{i})^{2}");
+        assert_eq!(print(r"\prod_{i=1}^{n} i \sin i"), r"\prod_{i = 1}^{n}{i \cdot \sin{i}}");
+        assert_eq!(print(r"\prod_{i=1}^{m} \prod_{j=1}^{n} ij"), r"\prod_{i = 1}^{m}{\prod_{j = 1}^{n}{i \cdot j}}");
+        assert_eq!(parse(r"\int_0^1 x^2 \di{x} + 1").unwrap(), r"(+ \int 1)");
+        assert_eq!(print(r"\int_a^b{x}\di{x} y"), r"\int_{a}^{b} x \cdot \di{x} \cdot y");
+        assert_eq!(print(r"\int_0^1 \sin x \mathrm{d}x"), r"\int_{0}^{1} \sin{x} \cdot \mathrm{d} \cdot x");
+        assert_eq!(print(r"\int_0^1 \int_0^x xy \di{y} \di{x}"), r"\int_{0}^{1} \int_{0}^{x} x \cdot y \cdot \di{y} \cdot \di{x}");
+        assert_eq!(parse(r"\prod_{i=1}^{n}").unwrap_err(), ParseError::MissingOperand(Span::new(0, 15)));
+    }
+
+    #[test]
+    fn superscript_test() {
+        assert_eq!(parse("2^{x+1}").unwrap(), "(^ 2 (+ x 1))");
+        assert_eq!(parse("2^3^2").unwrap(), "(^ 2 (^ 3 2))");
+        assert_eq!(parse(r"e^{-\frac{t}{\tau}}").unwrap(), r"(^ e (- \frac))");
     }
 }
@@ -0,0 +1,200 @@
+//! [crate::printer] turns an AST back into LaTeX, so we can show what landau understood from the input,
+//! or print an expression which is made by other features
+//!
+use crate::ast::{Node, NodeKind};
+use crate::lex::{Token, HUGE_SYMBOL, PREFIX_FUNCTION};
+
+// how tightly a printed node holds together, a node needs parentheses
+// if it is the operand of an operator which holds tighter
+const EQUATION: u8 = 1;
+const SUM: u8 = 3;
+const PRODUCT: u8 = 5;
+const UNARY: u8 = 7;
+const POWER: u8 = 9;
+const ATOM: u8 = 10;
+
+impl Node {
+    /// Print the node as canonical LaTeX with only the parentheses we need.
+    /// Division is always printed as \frac, multiplication is printed as \cdot,
+    /// except a number followed by a symbol, such as "2x".
+    /// Parsing the result gives the same tree
+    pub fn to_latex(&self) -> String {
+        match self.node_kind {
+            NodeKind::Num => self.value_to_latex(),
+            NodeKind::Unary => {
+                let operand = self.right.as_ref().unwrap();
+                let op = match Node::op_to_latex(&self.op) {
+                    // "\pm x" rather than "\pmx"
+                    op if op.starts_with('\\') => format!("{op} "),
+                    op => op.to_string(),
+                };
+                // "-(-x)" rather than "--x"
+                format!("{op}{}", operand.wrap(operand.precedence() <= UNARY))
+            }
+            NodeKind::Equation => {
+                format!("{} = {}", self.left.as_ref().unwrap().to_latex(), self.right.as_ref().unwrap().to_latex())
+            }
+            NodeKind::Op => {
+                let left = self.left.as_ref().unwrap();
+                let right = self.right.as_ref().unwrap();
+
+                match self.op {
+                    Some(Token::Div) => format!("\\frac{{{}}}{{{}}}", left.to_latex(), right.to_latex()),
+                    Some(Token::Superscript(_)) => {
+                        let is_symbol = left.node_kind == NodeKind::Num
+                            && matches!(left.value, Some(Token::Expression(_)))
+                            && left.precedence() == ATOM;
+                        format!("{}^{{{}}}", left.wrap(!is_symbol), right.to_latex())
+                    }
+                    _ => {
+                        let precedence = self.precedence();
+                        let l = left.wrap(left.precedence() < precedence);
+                        // operators are left-associative, and a unary operator is clearer in parentheses
+                        let r = right.wrap(right.precedence() <= precedence || right.precedence() == UNARY);
+
+                        if self.op == Some(Token::Times) && left.is_number() && matches!(right.value, Some(Token::Unit(_))) {
+                            // a thin space between a number and its unit, such as "3\,\mathrm{m}"
+                            format!("{l}\\,{r}")
+                        } else if self.op == Some(Token::Times) && left.is_number() && r.starts_with(|c: char| !c.is_ascii_digit()) {
+                            format!("{l}{r}")
+                        } else {
+                            format!("{l} {} {r}", Node::op_to_latex(&self.op))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn value_to_latex(&self) -> String {
+        match &self.value {
+            Some(Token::Expression(e)) => e.clone(),
+            Some(Token::Unit(u)) => format!("\\mathrm{{{u}}}"),
+            // Caution: for huge symbols, optional_args[0] stands for subscript and [1] for superscript
+            Some(Token::Function(fun, _, _)) if HUGE_SYMBOL.contains(fun) => {
+                let limits: Vec<String> = self.optional_args.iter().map(|a| a.to_latex()).collect();
+                // the body is in braces, so whatever follows is not a part of it,
+                // but the integrand ends at its differential by itself
+                let body: String = self.required_args.iter()
+                    .map(|a| if fun == "int" { format!(" {}", a.to_latex()) } else { format!("{{{}}}", a.to_latex()) })
+                    .collect();
+                match limits.as_slice() {
+                    [sub, sup] => format!("\\{fun}_{{{sub}}}^{{{sup}}}{body}"),
+                    _ => format!("\\{fun}{body}"),
+                }
+            }
+            // the base of a logarithm, such as \log_{2}{x}
+            Some(Token::Function(fun, _, _)) if PREFIX_FUNCTION.contains(fun) && self.optional_args.len() == 1 => {
+                let args: String = self.required_args.iter().map(|a| format!("{{{}}}", a.to_latex())).collect();
+                format!("\\{fun}_{{{}}}{args}", self.optional_args[0].to_latex())
+            }
+            Some(Token::Function(fun, _, _)) => {
+                let mut s = format!("\\{fun}");
+                for arg in self.optional_args.iter() {
+                    s.push_str(&format!("[{}]", arg.to_latex()));
+                }
+                for arg in self.required_args.iter() {
+                    s.push_str(&format!("{{{}}}", arg.to_latex()));
+                }
+                s
+            }
+            _ => String::new(),
+        }
+    }
+
+    fn op_to_latex(op: &Option<Token>) -> &'static str {
+        match op {
+            Some(Token::Add) => "+",
+            Some(Token::Sub) => "-",
+            Some(Token::PlusMinus) => "\\pm",
+            Some(Token::Times) => "\\cdot",
+            Some(Token::Equal) => "=",
+            _ => "",
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self.node_kind {
+            // a number made by other features may be negative
+            NodeKind::Num if self.to_latex().starts_with('-') => UNARY,
+            NodeKind::Num => ATOM,
+            NodeKind::Unary => UNARY,
+            NodeKind::Equation => EQUATION,
+            NodeKind::Op => match self.op {
+                Some(Token::Times) => PRODUCT,
+                Some(Token::Superscript(_)) => POWER,
+                // \frac has its own braces
+                Some(Token::Div) => ATOM,
+                _ => SUM,
+            },
+        }
+    }
+
+    /// A number, or a negative one such as "-2", which can be followed by a symbol directly
+    fn is_number(&self) -> bool {
+        match self.node_kind {
+            NodeKind::Unary => self.op == Some(Token::Sub) && self.right.as_ref().unwrap().is_number(),
+            _ => matches!(&self.value, Some(Token::Expression(e)) if e.starts_with(|c: char| c.is_ascii_digit())),
+        }
+    }
+
+    fn wrap(&self, parentheses: bool) -> String {
+        if parentheses {
+            format!("({})", self.to_latex())
+        } else {
+            self.to_latex()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::AST;
+    use crate::lex::Lex;
+
+    fn print(s: &str) -> String {
+        AST::new(Lex::new(s.to_string()).parse().unwrap()).unwrap().0.to_latex()
+    }
+
+    #[test]
+    fn to_latex_test() {
+        assert_eq!(print("a+b*c"), r"a + b \cdot c");
+        assert_eq!(print("(a+b)(a-b)"), r"(a + b) \cdot (a - b)");
+        assert_eq!(print("a-(b-c)"), "a - (b - c)");
+        assert_eq!(print("(a-b)-c"), "a - b - c");
+        assert_eq!(print("2x + 2\\pi r"), r"2x + 2\pi \cdot r");
+        assert_eq!(print("2 * 3"), r"2 \cdot 3");
+        assert_eq!(print("a/b/c"), r"\frac{\frac{a}{b}}{c}");
+        assert_eq!(print("-x^2"), "-x^{2}");
+        assert_eq!(print("(-x)^2 + a * -b"), r"(-x)^{2} + a \cdot (-b)");
+        assert_eq!(print("{a+b}^{c+1}"), "(a + b)^{c + 1}");
+        assert_eq!(print("2^3^2"), "2^{3^{2}}");
+        assert_eq!(print(r"\sqrt[3]{\frac{1}{2}}"), r"\sqrt[3]{\frac{1}{2}}");
+        assert_eq!(print(r"x = -b \pm \sqrt{b^2 - 4ac}"), r"x = -b \pm \sqrt{b^{2} - 4a \cdot c}");
+        assert_eq!(print(r"\mu{}c_p + T_0"), r"\mu \cdot c_p + T_0");
+        assert_eq!(print(r"x = \pm 2"), r"x = \pm 2");
+        assert_eq!(print(r"-2x"), r"-2x");
+        assert_eq!(print(r"\sum_{i=1}^{n} i^2 + 1"), r"\sum_{i = 1}^{n}{i^{2}} + 1");
+        assert_eq!(print(r"3\,\mathrm{m/s} \cdot t"), r"3\,\mathrm{m/s} \cdot t");
+    }
+
+    #[test]
+    fn round_trip_test() {
+        let inputs = [
+            r"a - (b - c) \cdot d^{2}",
+            r"\frac{-b + \sqrt{b^2 - 4ac}}{2a}",
+            r"-(-x) + (+y)",
+            r"e^{-\frac{t}{\tau}} = 2^{x+1}",
+            r"\sqrt{a}^2 \pm 1",
+            r"\frac{1\,\mathrm{km}}{2\mathrm{h}}",
+            r"\sin^2 x + \log_2 8x \cos x",
+            r"2\prod_{i=1}^{n} (1 + \frac{1}{i}) x + 1",
+            r"\int_0^1 \int_0^x xy \di{y} \di{x} \cdot 2",
+        ];
+
+        for input in inputs {
+            let once = print(input);
+            assert_eq!(print(&once), once);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! [crate::span] records where a token or a node comes from in the original LaTeX input,
+//! so that errors can point at the exact place which goes wrong
+//!
+use std::ops::Range;
+
+/// A half-open range [start, end) of char offsets in the input of [crate::lex::Lex].
+/// We count chars rather than bytes because [crate::lex::Lex] reads the input char by char,
+/// use [Span::byte_range] if you need to slice the original string
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Something together with the place it comes from
+#[derive(PartialEq, Debug, Clone)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(value: T, span: Span) -> Self {
+        Spanned { value, span }
+    }
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// The smallest span which covers both spans
+    pub fn merge(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    /// Move the span by offset, used when a piece of the input is lexed on its own
+    pub fn shift(self, offset: usize) -> Span {
+        Span::new(self.start + offset, self.end + offset)
+    }
+
+    /// Convert the char offsets to byte offsets of source
+    pub fn byte_range(&self, source: &str) -> Range<usize> {
+        let byte_at = |n: usize| {
+            source.char_indices().nth(n).map(|(i, _)| i).unwrap_or(source.len())
+        };
+
+        byte_at(self.start)..byte_at(self.end)
+    }
+
+    /// Render the line of source which contains the span, with carets under the spanned chars.
+    /// For example, the span of 'x' in "a + x" is rendered as
+    /// ```text
+    /// 1 | a + x
+    ///   |     ^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let mut line_start = 0;
+        let mut line_number = 1;
+        for (i, ch) in source.chars().enumerate() {
+            if i >= self.start {
+                break;
+            }
+            if ch == '\n' {
+                line_start = i + 1;
+                line_number += 1;
+            }
+        }
+
+        let line: String = source.chars().skip(line_start)
+            .take_while(|ch| *ch != '\n').collect();
+        let column = self.start - line_start;
+        let line_len = line.chars().count();
+        // a span may cross lines or point at the end of input, just underline what we can see
+        let width = self.end.saturating_sub(self.start)
+            .min(line_len.saturating_sub(column))
+            .max(1);
+
+        let number = line_number.to_string();
+        let padding = " ".repeat(number.len());
+        format!("{number} | {line}\n{padding} | {}{}", " ".repeat(column), "^".repeat(width))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::span::Span;
+
+    #[test]
+    fn byte_range_test() {
+        let s = r"\mu{}x + \rho{}y";
+        let span = Span::new(5, 6);
+
+        assert_eq!(&s[span.byte_range(s)], "x");
+        assert_eq!(Span::new(0, 3).byte_range("μ+x"), 0..4);
+    }
+
+    #[test]
+    fn render_test() {
+        assert_eq!(Span::new(4, 5).render("a + x"), "1 | a + x\n  |     ^");
+        assert_eq!(Span::new(2, 5).render("a\nbcd"), "2 | bcd\n  | ^^^");
+    }
+}
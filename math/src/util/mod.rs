@@ -1,3 +1,4 @@
 pub mod approx;
 pub mod pow;
 pub mod root;
+pub mod solve;
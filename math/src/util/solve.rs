@@ -0,0 +1,116 @@
+/// Find x which makes f(x) = 0, starting from guess.
+/// f returns None where it can not be calculated, such as 1/x at 0.
+///
+/// The secant method is tried first since it is fast and can find roots which f only touches,
+/// such as the root of x^2. If it fails, we search around guess for a sign change of f
+/// and bisect the interval.
+/// Caution: if there are several roots, the one we find is not always the nearest one
+pub fn find_root<F>(mut f: F, guess: f64) -> Option<f64>
+    where
+        F: FnMut(f64) -> Option<f64>,
+{
+    secant(&mut f, guess).or_else(|| bracket(&mut f, guess))
+}
+
+const TOLERANCE: f64 = 1e-12;
+const MAX_ITERATION: usize = 100;
+
+fn is_root(x: f64, fx: f64) -> bool {
+    fx.abs() <= TOLERANCE * x.abs().max(1.0)
+}
+
+fn secant<F>(f: &mut F, guess: f64) -> Option<f64>
+    where
+        F: FnMut(f64) -> Option<f64>,
+{
+    let mut x0 = guess;
+    let mut x1 = guess + 1e-3 * guess.abs().max(1.0);
+    let mut f0 = f(x0)?;
+    if is_root(x0, f0) {
+        return Some(x0);
+    }
+
+    for _ in 0..MAX_ITERATION {
+        let f1 = f(x1)?;
+        if is_root(x1, f1) {
+            return Some(x1);
+        }
+        if f1 == f0 {
+            return None;
+        }
+
+        let x2 = x1 - f1 * (x1 - x0) / (f1 - f0);
+        if !x2.is_finite() {
+            return None;
+        }
+        (x0, f0, x1) = (x1, f1, x2);
+    }
+
+    None
+}
+
+fn bracket<F>(f: &mut F, guess: f64) -> Option<f64>
+    where
+        F: FnMut(f64) -> Option<f64>,
+{
+    let mut step = 1e-3 * guess.abs().max(1.0);
+
+    // walk away from guess on both sides, with doubled step every time
+    for _ in 0..MAX_ITERATION {
+        for (a, b) in [(guess - step, guess - step / 2.0), (guess + step / 2.0, guess + step)] {
+            if let (Some(fa), Some(fb)) = (f(a), f(b)) {
+                if fa.signum() != fb.signum() {
+                    return bisect(f, a, b, fa);
+                }
+            }
+        }
+        step *= 2.0;
+        if !step.is_finite() {
+            break;
+        }
+    }
+
+    None
+}
+
+fn bisect<F>(f: &mut F, mut a: f64, mut b: f64, mut fa: f64) -> Option<f64>
+    where
+        F: FnMut(f64) -> Option<f64>,
+{
+    for _ in 0..MAX_ITERATION * 2 {
+        let mid = (a + b) / 2.0;
+        let fm = f(mid)?;
+        if is_root(mid, fm) || mid == a || mid == b {
+            return Some(mid);
+        }
+
+        if fm.signum() == fa.signum() {
+            (a, fa) = (mid, fm);
+        } else {
+            b = mid;
+        }
+    }
+
+    Some((a + b) / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::approx::custom_approx;
+    use crate::util::solve::find_root;
+
+    #[test]
+    fn find_root_test() {
+        let a = find_root(|x| Some(x * x - 3.0), 1.0).unwrap();
+        assert_eq!(custom_approx(a, 6).unwrap(), 1.732051);
+
+        let b = find_root(|x| Some(x * x), 1.0).unwrap();
+        assert_eq!(custom_approx(b, 3).unwrap(), 0.0);
+
+        // the secant method jumps between two sides of the root of x^(1/3) and never converges
+        let c = find_root(|x| Some(x.cbrt()), 3.0).unwrap();
+        assert_eq!(custom_approx(c, 6).unwrap(), 0.0);
+
+        assert!(find_root(|x| Some(x * x + 1.0), 0.0).is_none());
+    }
+}
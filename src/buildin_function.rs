@@ -1,28 +1,87 @@
+use std::collections::HashMap;
+use std::f64::consts;
+use std::sync::Arc;
 use lazy_static::lazy_static;
+use latex_analyzer::span::Span;
 use math::util::root::nth_root;
-use crate::function::Function;
-
-pub(crate) static mut EXTERN_FUNCTION: Vec<Function> = Vec::new();
+use crate::error::EvalError;
+use crate::config::{AngleUnit, CONFIG};
+use crate::function::{check_arity, Container, Function};
 
 lazy_static! {
-    pub static ref BUILD_IN_FUNCTION: Vec<Function> = {
+    pub static ref BUILD_IN_FUNCTION: Vec<Arc<Function>> = {
         let mut table = Vec::new();
         table.push(Function::new("frac", |_, r| {
+            check_arity("frac", &r, 2)?;
             div(r[0].get_value(), r[1].get_value())
         }));
         table.push(Function::new("sqrt", |o, r| {
-            nth_root(r[0].get_value(), o[0].get_value() as i32)
+            check_arity("sqrt", &r, 1)?;
+            // \sqrt{x} is the square root, just like \sqrt[2]{x}
+            let n = match o.first().map(|n| n.get_value()) {
+                None => 2,
+                Some(n) if n != 0.0 && n.fract() == 0.0 && n.abs() <= i32::MAX as f64 => n as i32,
+                Some(n) => {
+                    return Err(domain_error(format!("The index of a root should be a non-zero integer, but gets {n}")));
+                }
+            };
+            let x = r[0].get_value();
+            match nth_root(x.abs(), n) {
+                // an odd root of a negative number is negative, such as \sqrt[3]{-8}
+                Some(root) if x < 0.0 && n % 2 != 0 => Ok(-root),
+                Some(root) if x >= 0.0 => Ok(root),
+                _ => Err(domain_error(format!("Can not calculate the {n}th root of {x}"))),
+            }
         }));
-        table.push(Function::new("int", |o, r| {
-            let r = r.iter()
-                .map(|x| x.get_value()).collect();
-            int(o[0].get_value(), o[1].get_value(), r)
+        table.push(Function::new("ln", |_, r| {
+            check_arity("ln", &r, 1)?;
+            let x = r[0].get_value();
+            if x > 0.0 {
+                Ok(x.ln())
+            } else {
+                Err(domain_error(format!("Can not calculate the logarithm of {x}")))
+            }
         }));
-        table.push(Function::new("sum", |_, r| {
-            let r = r.iter()
-                .map(|x| x.get_value()).collect();
-            sum(r)
+        // \log_2 x has the base 2 as its optional argument, and \log x is the common logarithm
+        table.push(Function::new("log", |o, r| {
+            let base = o.first().map_or(10.0, |b| b.get_value());
+            if base <= 0.0 || base == 1.0 {
+                return Err(domain_error(format!("Can not use {base} as the base of a logarithm")));
+            }
+            elementary("log", r, |x| if x > 0.0 { x.ln() / base.ln() } else { f64::NAN })
         }));
+        table.push(Function::new("exp", |_, r| elementary("exp", r, f64::exp)));
+
+        table.extend(angle_functions(CONFIG.angle_unit));
+
+        table.push(Function::new("sinh", |_, r| elementary("sinh", r, f64::sinh)));
+        table.push(Function::new("cosh", |_, r| elementary("cosh", r, f64::cosh)));
+        table.push(Function::new("tanh", |_, r| elementary("tanh", r, f64::tanh)));
+        table.push(Function::new("coth", |_, r| elementary("coth", r, |x| reciprocal(x.tanh()))));
+        table.push(Function::new("arsinh", |_, r| elementary("arsinh", r, f64::asinh)));
+        table.push(Function::new("arcosh", |_, r| elementary("arcosh", r, f64::acosh)));
+        table.push(Function::new("artanh", |_, r| elementary("artanh", r, |x| if x.abs() < 1.0 { x.atanh() } else { f64::NAN })));
+
+        table.into_iter().map(Arc::new).collect()
+    };
+}
+
+lazy_static! {
+    // trigonometric functions for every angle unit, an [crate::exec::Exec] may use another unit than the config
+    pub static ref ANGLE_FUNCTION: HashMap<AngleUnit, Vec<Arc<Function>>> = {
+        [AngleUnit::Radian, AngleUnit::Degree].into_iter()
+            .map(|unit| (unit, angle_functions(unit).into_iter().map(Arc::new).collect()))
+            .collect()
+    };
+}
+
+lazy_static! {
+    // Constants which can be used without \var, a \var with the same name takes the priority
+    pub static ref BUILD_IN_CONSTANT: HashMap<&'static str, f64> = {
+        let mut table = HashMap::new();
+        table.insert("\\pi", consts::PI);
+        table.insert("e", consts::E);
+        table.insert("\\infty", f64::INFINITY);
 
         table
     };
@@ -34,38 +93,70 @@ lazy_static! {
     };
 }
 
-/// a / b
-pub(crate) fn div(a: f64, b: f64) -> Option<f64> {
-    return if b == 0.0 {
-        None
-    } else {
-        Some(a / b)
+lazy_static! {
+    // names which the lexer, the parser or exec read by themselves, a function with one of them is never called
+    pub static ref RESERVED_NAME: Vec<String> = {
+        ["var", "mathrm", "di", "pa", "partial", "cdot", "times", "div", "pm", "left", "right", "quad", "qquad"]
+            .iter().map(|s| s.to_string())
+            .chain(HUGE_SYMBOL.iter().cloned())
+            .collect()
     };
 }
 
-/// By Simpson`s method of calculate an integration
-/// \int_a^b{}f(x)\d{}x \approx \frac{b - a}{6}\left(f(a) + 4f\left(\frac{a + b}{2}\right) + f(b)\right)
-pub(crate) fn int(lo: f64, up: f64, po: Vec<f64>) -> Option<f64> {
-    if po.len() < 3 {
-        return None;
-    }
-
-    let fa = (up - lo) / 6.0;
-    let nu = po[0] + 4.0 * po[1] + po[2];
-
-    Some(fa * nu)
+/// The function does not know where it is called, [crate::exec::Exec] will tell the span
+fn domain_error(message: String) -> EvalError {
+    EvalError::DomainError(message, Span::default())
 }
 
-pub(crate) fn sum(po: Vec<f64>) -> Option<f64> {
-    if po.is_empty() {
-        return None;
+/// A function of one argument, whose result must be a finite number.
+/// f returns NaN out of its domain, such as \arcsin{2} or \cot{0}, and infinity only if the result is too large
+fn elementary<F>(name: &str, r: Container, f: F) -> Result<f64, EvalError>
+    where
+        F: Fn(f64) -> f64,
+{
+    check_arity(name, &r, 1)?;
+    let x = r[0].get_value();
+    let result = f(x);
+
+    if result.is_nan() {
+        Err(domain_error(format!("Can not calculate \\{name} of {x}")))
+    } else if result.is_infinite() && x.is_finite() {
+        Err(EvalError::Overflow(format!("\\{name} of {x} is too large"), Span::default()))
+    } else {
+        Ok(result)
     }
+}
 
-    Some(po.iter().sum())
+/// 1 / x, which is NaN at the pole x = 0 rather than infinity
+fn reciprocal(x: f64) -> f64 {
+    if x == 0.0 { f64::NAN } else { 1.0 / x }
 }
 
-pub fn int_auto_filler(fun: fn(f64) -> f64, lo: f64, up: f64) -> Vec<f64> {
-    vec![fun(lo), fun((lo + up) / 2.0), fun(up)]
+/// Trigonometric functions take angles in unit, and their inverses give angles in it
+fn angle_functions(unit: AngleUnit) -> Vec<Function> {
+    let radian = move |angle: f64| unit.to_radian(angle);
+    let angle = move |radian: f64| unit.in_unit(radian);
+
+    vec![
+        Function::new("sin", move |_, r| elementary("sin", r, |x| radian(x).sin())),
+        Function::new("cos", move |_, r| elementary("cos", r, |x| radian(x).cos())),
+        Function::new("tan", move |_, r| elementary("tan", r, |x| radian(x).tan())),
+        Function::new("cot", move |_, r| elementary("cot", r, |x| reciprocal(radian(x).tan()))),
+        Function::new("sec", move |_, r| elementary("sec", r, |x| reciprocal(radian(x).cos()))),
+        Function::new("csc", move |_, r| elementary("csc", r, |x| reciprocal(radian(x).sin()))),
+        Function::new("arcsin", move |_, r| elementary("arcsin", r, |x| angle(x.asin()))),
+        Function::new("arccos", move |_, r| elementary("arccos", r, |x| angle(x.acos()))),
+        Function::new("arctan", move |_, r| elementary("arctan", r, |x| angle(x.atan()))),
+    ]
+}
+
+/// a / b
+pub(crate) fn div(a: f64, b: f64) -> Result<f64, EvalError> {
+    return if b == 0.0 {
+        Err(domain_error(format!("Can not divide {a} by zero")))
+    } else {
+        Ok(a / b)
+    };
 }
 
 /// caution: if lo > up, the function will produce an empty Vec
@@ -81,23 +172,12 @@ pub fn sum_auto_filler(fun: fn(i32) -> f64, lo: i32, up: i32) -> Vec<f64> {
 
 #[cfg(test)]
 mod tests {
-    use math::util::approx::custom_approx;
-    use crate::buildin_function::{div, int, int_auto_filler};
+    use crate::buildin_function::div;
+    use crate::error::EvalError;
 
     #[test]
     fn div_test() {
         assert_eq!(div(1.0, 2.0).unwrap(), 0.5);
-        assert!(div(1.0, 0.0).is_none());
-    }
-
-    #[test]
-    fn int_test() {
-        let fun = |x: f64| x * x;
-        let lo = 1.0;
-        let up = 2.0;
-        let nu = int_auto_filler(fun, lo, up);
-        let re = custom_approx(int(lo, up, nu).unwrap(), 3).unwrap();
-
-        assert_eq!(re, 2.333);
+        assert!(matches!(div(1.0, 0.0), Err(EvalError::DomainError(_, _))));
     }
 }
@@ -0,0 +1,449 @@
+//! [crate::compile] turns an expression into a flat program of a stack machine.
+//! Functions, numbers and variables are all resolved when compiling,
+//! so calculating the program again and again is much cheaper than walking the AST.
+//! A program does not know units, so an expression with units, even in \var, is never compiled
+//! and [crate::exec::Exec] calculates it instead
+//!
+use std::collections::HashMap;
+use std::sync::Arc;
+use latex_analyzer::ast::{Node, NodeKind};
+use latex_analyzer::lex::Token;
+use latex_analyzer::span::Span;
+use crate::buildin_function::{BUILD_IN_CONSTANT, HUGE_SYMBOL};
+use crate::config;
+use crate::definition::Definition;
+use crate::error::EvalError;
+use crate::exec::{Bindings, Columns};
+use crate::function::{Function, FunctionRegistry};
+use crate::known::Known;
+use crate::transformer::string_to_known;
+use crate::unit::Quantity;
+
+#[derive(Debug, Clone)]
+enum Instruction {
+    Push(f64),
+    // push the value of the i-th variable
+    Load(usize),
+    Neg,
+    // operands are popped from the stack, and the i-th site tells where the operator is when it goes wrong
+    Add(usize),
+    Sub(usize),
+    Times(usize),
+    Div(usize),
+    Pow(usize),
+    Call {
+        fun: Arc<Function>,
+        optional: usize,
+        required: usize,
+        span: Span,
+    },
+}
+
+/// An expression compiled by [crate::exec::Exec::compile]
+#[derive(Debug, Clone)]
+pub struct Program {
+    code: Vec<Instruction>,
+    // the LaTeX and span of operators, only used to report errors
+    sites: Vec<(String, Span)>,
+    variables: Vec<String>,
+    // values from \var or built-in constants, None if the variable must be bound
+    defaults: Vec<Option<f64>>,
+    spans: Vec<Span>,
+    // variables defined by \var with other variables, the code calculates the value of the slot
+    definitions: Vec<(usize, Vec<Instruction>)>,
+    stack_size: usize,
+}
+
+impl Program {
+    pub(crate) fn new(node: &Node, var_map: &HashMap<String, Quantity>, definitions: &[Definition],
+                      functions: &FunctionRegistry) -> Result<Program, EvalError> {
+        let mut program = Program {
+            code: Vec::new(),
+            sites: Vec::new(),
+            variables: Vec::new(),
+            defaults: Vec::new(),
+            spans: Vec::new(),
+            definitions: Vec::new(),
+            stack_size: 0,
+        };
+        // nor values from \var with units, such as l in \sin{l}\var{l=1\,\mathrm{m}}
+        let with_unit = definitions.iter()
+            .find(|d| var_map.get(&d.name).is_some_and(|q| !q.dimension.is_dimensionless()));
+        if let Some(definition) = with_unit {
+            return Err(EvalError::Unsupported(
+                format!("Can not compile {} with its unit, try calculate it by Exec", definition.name), definition.span,
+            ));
+        }
+
+        let mut code = Vec::new();
+        program.compile(&mut code, node, var_map, functions, 0)?;
+        program.code = code;
+
+        // definitions only with numbers never change, so their values are just defaults,
+        // but the ones which use variables to be bound are calculated, such as A in \var{A=\pi r^2} without r
+        let calculated = |d: &&Definition| !d.dependencies.is_empty() || !var_map.contains_key(&d.name);
+        for definition in definitions.iter().filter(calculated) {
+            let mut code = Vec::new();
+            program.compile(&mut code, &definition.node, var_map, functions, 0)?;
+            let slot = program.variable(&definition.name, var_map, definition.span);
+            program.definitions.push((slot, code));
+        }
+
+        Ok(program)
+    }
+
+    /// Names of variables, [Program::run] takes their values in the same order
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// The index of a variable in [Program::variables]
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.variables.iter().position(|v| v == name)
+    }
+
+    /// Values of variables from bindings, \var and built-in constants, in the order of [Program::variables].
+    /// Get them once and change some of them between runs, so no name is looked up in the loop
+    pub fn values(&self, bindings: &Bindings) -> Result<Vec<f64>, EvalError> {
+        let definitions = self.unbound_definitions(|name| bindings.vars().contains_key(name));
+        // slots of definitions are calculated, they have no default if they use variables to be bound
+        let skipped: Vec<usize> = definitions.iter().map(|(slot, _)| *slot).collect();
+        let mut values = self.resolve(bindings, &skipped)?;
+        for (slot, code) in definitions {
+            values[*slot] = self.execute(code, &values)?;
+        }
+
+        Ok(values)
+    }
+
+    /// Definitions which should be calculated, since the variables they define are not bound
+    fn unbound_definitions<F>(&self, is_bound: F) -> Vec<&(usize, Vec<Instruction>)>
+        where
+            F: Fn(&str) -> bool,
+    {
+        self.definitions.iter()
+            .filter(|(slot, _)| !is_bound(&self.variables[*slot]))
+            .collect()
+    }
+
+    /// Values of variables, but the ones in skipped slots are left 0 since they will be given later
+    fn resolve(&self, bindings: &Bindings, skipped: &[usize]) -> Result<Vec<f64>, EvalError> {
+        self.variables.iter().zip(&self.defaults).zip(&self.spans)
+            .enumerate()
+            .map(|(i, ((name, default), span))| {
+                if skipped.contains(&i) {
+                    return Ok(0.0);
+                }
+                bindings.vars().get(name).copied().or(*default)
+                    .ok_or_else(|| EvalError::UndefinedVariable(name.clone(), *span))
+            })
+            .collect()
+    }
+
+    pub fn calculate(&self) -> Result<f64, EvalError> {
+        self.calculate_with(&Bindings::new())
+    }
+
+    pub fn calculate_with(&self, bindings: &Bindings) -> Result<f64, EvalError> {
+        self.run(&self.values(bindings)?)
+    }
+
+    /// Calculate once for every row of columns, see [Columns]
+    pub fn calculate_columns(&self, columns: &Columns) -> Result<Vec<f64>, EvalError> {
+        let rows = columns.len()?;
+        // columns are put into the slots of the variables, the other slots stay the same for every row
+        let slots: Vec<(usize, &[f64])> = columns.columns().iter()
+            .filter_map(|(name, values)| Some((self.slot(name)?, *values)))
+            .collect();
+        let definitions = self.unbound_definitions(|name| {
+            columns.scalars().vars().contains_key(name) || columns.columns().iter().any(|(n, _)| n == name)
+        });
+        let skipped: Vec<usize> = slots.iter().map(|(slot, _)| *slot)
+            .chain(definitions.iter().map(|(slot, _)| *slot))
+            .collect();
+        let mut values = self.resolve(columns.scalars(), &skipped)?;
+
+        (0..rows)
+            .map(|row| {
+                for (slot, column) in slots.iter() {
+                    values[*slot] = column[row];
+                }
+                for (slot, code) in definitions.iter() {
+                    values[*slot] = self.execute(code, &values)?;
+                }
+                self.run(&values)
+            })
+            .collect()
+    }
+
+    /// Calculate with values of variables in the order of [Program::variables].
+    /// Caution: variables defined by \var with others are not calculated again, such as A in \var{A=\pi r^2}
+    /// when the value of r is changed, use [Program::values] to get them
+    pub fn run(&self, values: &[f64]) -> Result<f64, EvalError> {
+        if values.len() != self.variables.len() {
+            return Err(EvalError::Unsupported(
+                format!("The program needs {} values, but gets {}", self.variables.len(), values.len()),
+                Span::default(),
+            ));
+        }
+
+        self.execute(&self.code, values)
+    }
+
+    fn execute(&self, code: &[Instruction], values: &[f64]) -> Result<f64, EvalError> {
+        let mut stack: Vec<f64> = Vec::with_capacity(self.stack_size);
+        for instruction in code.iter() {
+            let value = match instruction {
+                Instruction::Push(x) => *x,
+                Instruction::Load(i) => values[*i],
+                Instruction::Neg => -stack.pop().unwrap(),
+                Instruction::Add(site) => self.binary(&mut stack, *site, |a, b| Ok(a + b))?,
+                Instruction::Sub(site) => self.binary(&mut stack, *site, |a, b| Ok(a - b))?,
+                Instruction::Times(site) => self.binary(&mut stack, *site, |a, b| Ok(a * b))?,
+                Instruction::Div(site) => self.binary(&mut stack, *site, |a, b| {
+                    if b == 0.0 {
+                        Err(format!("Can not divide {a} by zero"))
+                    } else {
+                        Ok(a / b)
+                    }
+                })?,
+                Instruction::Pow(site) => self.binary(&mut stack, *site, |a, b| Ok(pow(a, b)))?,
+                Instruction::Call { fun, optional, required, span } => {
+                    let re: Vec<Box<dyn Known>> = stack.drain(stack.len() - *required..)
+                        .map(|x| Box::new(x) as Box<dyn Known>).collect();
+                    let op: Vec<Box<dyn Known>> = stack.drain(stack.len() - *optional..)
+                        .map(|x| Box::new(x) as Box<dyn Known>).collect();
+                    (fun.calc)(op, re).map_err(|e| e.at(*span))?
+                }
+            };
+            stack.push(value);
+        }
+
+        Ok(stack.pop().unwrap())
+    }
+
+    fn binary<F>(&self, stack: &mut Vec<f64>, site: usize, f: F) -> Result<f64, EvalError>
+        where
+            F: Fn(f64, f64) -> Result<f64, String>,
+    {
+        let right = stack.pop().unwrap();
+        let left = stack.pop().unwrap();
+        let (latex, span) = &self.sites[site];
+
+        let result = f(left, right).map_err(|message| EvalError::DomainError(message, *span))?;
+        // such as a fractional power of a negative number
+        if result.is_nan() && !left.is_nan() && !right.is_nan() {
+            return Err(EvalError::DomainError(format!("The result of {latex} is not a number"), *span));
+        }
+
+        Ok(result)
+    }
+
+    /// Append the instructions of node, depth is how many values are already on the stack
+    fn compile(&mut self, code: &mut Vec<Instruction>, node: &Node, var_map: &HashMap<String, Quantity>,
+               functions: &FunctionRegistry, depth: usize) -> Result<(), EvalError> {
+        self.stack_size = self.stack_size.max(depth + 1);
+
+        if node.node_kind == NodeKind::Num {
+            return match node.value.as_ref() {
+                // the index or the variable of integration changes while the body is calculated,
+                // which a flat program can not do
+                Some(Token::Function(name, _, _)) if HUGE_SYMBOL.contains(name) => Err(EvalError::Unsupported(
+                    format!("Can not compile \\{name}, try calculate it by Exec"), node.span,
+                )),
+                Some(Token::Function(name, _, _)) => {
+                    let fun = functions.find(name).map_err(|e| e.at(node.span))?;
+                    for (i, arg) in node.optional_args.iter().chain(&node.required_args).enumerate() {
+                        self.compile(code, arg, var_map, functions, depth + i)?;
+                    }
+                    code.push(Instruction::Call {
+                        fun,
+                        optional: node.optional_args.len(),
+                        required: node.required_args.len(),
+                        span: node.span,
+                    });
+                    Ok(())
+                }
+                // a program does not know units, so it could not tell 1\,\mathrm{m} + 1\,\mathrm{s} is wrong
+                Some(Token::Unit(unit)) => Err(EvalError::Unsupported(
+                    format!("Can not compile \\mathrm{{{unit}}}, try calculate it by Exec"), node.span,
+                )),
+                Some(Token::Expression(expr)) => {
+                    let instruction = match string_to_known(expr) {
+                        Ok(x) => Instruction::Push(x.get_value()),
+                        Err(_) => Instruction::Load(self.variable(expr, var_map, node.span)),
+                    };
+                    code.push(instruction);
+                    Ok(())
+                }
+                value => Err(EvalError::Unsupported(format!("Can not evaluate {value:?}"), node.span)),
+            };
+        }
+
+        let Some(ref op) = node.op else {
+            return Err(EvalError::Unsupported(format!("Can not get op from {:?}", node), node.span));
+        };
+        let plus_minus = || EvalError::Unsupported("\\pm gives two results, try calculate_all".to_string(), node.span);
+
+        match node.node_kind {
+            NodeKind::Unary => {
+                self.compile(code, node.right.as_ref().unwrap(), var_map, functions, depth)?;
+                match op {
+                    Token::Sub => code.push(Instruction::Neg),
+                    Token::Add => {}
+                    Token::PlusMinus => return Err(plus_minus()),
+                    o => return Err(EvalError::Unsupported(format!("Token {o:?} can not be a unary operator!"), node.span)),
+                }
+            }
+            NodeKind::Equation => {
+                return Err(EvalError::Unsupported(
+                    "Can not calculate an equation, try holds or solve_for".to_string(), node.span,
+                ));
+            }
+            _ => {
+                self.compile(code, node.left.as_ref().unwrap(), var_map, functions, depth)?;
+                self.compile(code, node.right.as_ref().unwrap(), var_map, functions, depth + 1)?;
+                let site = self.sites.len();
+                let instruction = match op {
+                    Token::Add => Instruction::Add(site),
+                    Token::Sub => Instruction::Sub(site),
+                    Token::Times => Instruction::Times(site),
+                    Token::Div => Instruction::Div(site),
+                    Token::Superscript(_) => Instruction::Pow(site),
+                    Token::PlusMinus => return Err(plus_minus()),
+                    o => return Err(EvalError::Unsupported(format!("Token {o:?} can not be a operator!"), node.span)),
+                };
+                self.sites.push((node.to_latex(), node.span));
+                code.push(instruction);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The slot of a variable, a \var with the same name takes the priority of a built-in constant
+    fn variable(&mut self, name: &str, var_map: &HashMap<String, Quantity>, span: Span) -> usize {
+        if let Some(i) = self.slot(name) {
+            return i;
+        }
+
+        self.variables.push(name.to_string());
+        self.defaults.push(var_map.get(name).map(|q| q.value).or_else(|| BUILD_IN_CONSTANT.get(name).copied()));
+        self.spans.push(span);
+        self.variables.len() - 1
+    }
+}
+
+/// a^b, shared by [crate::exec::Exec] and [Program]
+pub(crate) fn pow(base: f64, exponent: f64) -> f64 {
+    if config::CONFIG.high_accuracy {
+        math::util::pow::high_accuracy_pow(base, exponent)
+    } else {
+        // powi is faster and more accurate for integer exponents, including negative ones
+        match exponent {
+            r if r.fract() == 0.0 && r.abs() <= i32::MAX as f64 => base.powi(r as i32),
+            r => base.powf(r),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use latex_analyzer::lex::Lex;
+    use crate::error::EvalError;
+    use crate::exec::{Bindings, Columns, Exec};
+
+    fn exec(s: &str) -> Exec {
+        Exec::from_lex(Lex::new(s.to_string())).unwrap()
+    }
+
+    #[test]
+    fn compile_test() {
+        let cases = [
+            r"\frac{1}{2} + \sqrt[3]{4} - \frac{1}{3}",
+            r"2^3^2 - -a\var{a=2}",
+            r"(a+b)(a-b)\var{a=3}\var{b=1}",
+            r"\ln{e^2} + \pi x\var{x=2}",
+        ];
+        for s in cases {
+            let exec = exec(s);
+            assert_eq!(exec.compile().unwrap().calculate(), exec.calculate(), "{s}");
+        }
+    }
+
+    #[test]
+    fn run_test() {
+        let program = exec(r"a x^2 + 1\var{a=2}").compile().unwrap();
+        assert_eq!(program.variables(), ["a", "x"]);
+
+        let mut values = program.values(&Bindings::new().set("x", 0.0).unwrap()).unwrap();
+        let x = program.slot("x").unwrap();
+        let results: Vec<f64> = (0..3).map(|i| {
+            values[x] = i as f64;
+            program.run(&values).unwrap()
+        }).collect();
+        assert_eq!(results, vec![1.0, 3.0, 9.0]);
+
+        assert!(matches!(program.calculate(), Err(EvalError::UndefinedVariable(..))));
+        assert!(matches!(program.run(&[1.0]), Err(EvalError::Unsupported(..))));
+    }
+
+    #[test]
+    fn error_test() {
+        let exec1 = exec(r"\frac{1}{x} + (-1)^{y}\var{x=0}\var{y=0.5}");
+        let program = exec1.compile().unwrap();
+        assert!(matches!(program.calculate(), Err(EvalError::DomainError(..))));
+        // errors are the same as the ones of the tree walker, including where they are
+        let bindings = Bindings::new().set("x", 1.0).unwrap();
+        assert_eq!(program.calculate_with(&bindings), exec1.calculate_with(&bindings));
+
+        assert!(matches!(exec(r"\foo{1}").compile(), Err(EvalError::UnknownFunction(..))));
+        assert!(matches!(exec(r"1 \pm 2").compile(), Err(EvalError::Unsupported(..))));
+    }
+
+    #[test]
+    fn columns_test() {
+        let linear = exec(r"a x + y\var{a=2}");
+        let xs = [1.0, 2.0, 3.0];
+        let ys = [0.5, 0.0, -1.0];
+
+        let columns = Columns::new().column("x", &xs).unwrap().column("y", &ys).unwrap();
+        assert_eq!(linear.calculate_columns(&columns).unwrap(), vec![2.5, 4.0, 5.0]);
+
+        // scalars are broadcast to every row
+        let columns = Columns::new().column("x", &xs).unwrap().scalar("y", 1.0).unwrap().scalar("a", 1.0).unwrap();
+        assert_eq!(linear.calculate_columns(&columns).unwrap(), vec![2.0, 3.0, 4.0]);
+
+        let columns = Columns::new().column("x", &xs).unwrap().column("y", &ys[..2]).unwrap();
+        assert!(matches!(linear.calculate_columns(&columns), Err(EvalError::Unsupported(..))));
+        let columns = Columns::new().column("x", &xs).unwrap();
+        assert!(matches!(linear.calculate_columns(&columns), Err(EvalError::UndefinedVariable(..))));
+        let radii = [1.0, 2.0];
+        let columns = Columns::new().column("r", &radii).unwrap();
+        let area = exec(r"\frac{A}{\pi}\var{r=0}\var{A=\pi r^2}");
+        assert_eq!(area.calculate_columns(&columns).unwrap(), vec![1.0, 4.0]);
+
+        let columns = Columns::new().column("x", &[]).unwrap().scalar("y", 0.0).unwrap();
+        assert!(linear.calculate_columns(&columns).unwrap().is_empty());
+
+        // \sum can not be compiled, so every row is calculated by Exec
+        let sum = exec(r"\sum_{i=1}^{n} i^2 + c");
+        let ns = [1.0, 2.0, 10.0];
+        let columns = Columns::new().column("n", &ns).unwrap().scalar("c", 1.0).unwrap();
+        assert_eq!(sum.calculate_columns(&columns).unwrap(), vec![2.0, 6.0, 386.0]);
+        let columns = Columns::new().column("n", &ns).unwrap();
+        assert!(matches!(sum.calculate_columns(&columns), Err(EvalError::UndefinedVariable(..))));
+        let columns = Columns::new().column("n", &ns).unwrap().column("c", &ys[..2]).unwrap();
+        assert!(matches!(sum.calculate_columns(&columns), Err(EvalError::Unsupported(..))));
+
+        // units are not compiled, so they are still checked
+        let columns = Columns::new().column("x", &xs[..2]).unwrap();
+        let length = exec(r"x \cdot 1\,\mathrm{km}");
+        assert!(matches!(length.compile(), Err(EvalError::Unsupported(..))));
+        assert_eq!(length.calculate_columns(&columns).unwrap(), vec![1000.0, 2000.0]);
+        let err = |s: &str| exec(s).calculate_columns(&columns).unwrap_err();
+        assert!(matches!(err(r"x + 1\,\mathrm{m} + 1\,\mathrm{s}"), EvalError::DimensionError(..)));
+        assert!(matches!(err(r"\sin{1\,\mathrm{m}} + x"), EvalError::DimensionError(..)));
+        assert!(matches!(err(r"\sin{l} + x\var{l=1\,\mathrm{m}}"), EvalError::DimensionError(..)));
+    }
+}
@@ -1,9 +1,96 @@
 use lazy_static::lazy_static;
+use latex_analyzer::span::Span;
 use serde::Deserialize;
+use crate::error::EvalError;
 
 #[derive(Deserialize)]
 pub struct Config {
     pub high_accuracy: bool,
+    // the unit of angles taken by \sin and returned by \arcsin, radian if it is not set
+    #[serde(default)]
+    pub angle_unit: AngleUnit,
+    // how far the value of an integral may be from the exact one
+    #[serde(default = "default_integral_tolerance")]
+    pub integral_tolerance: f64,
+}
+
+fn default_integral_tolerance() -> f64 {
+    1e-10
+}
+
+#[derive(Deserialize, PartialEq, Eq, Hash, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum AngleUnit {
+    #[default]
+    Radian,
+    Degree,
+}
+
+impl AngleUnit {
+    /// Convert an angle in this unit to radian
+    pub fn to_radian(self, angle: f64) -> f64 {
+        match self {
+            AngleUnit::Radian => angle,
+            AngleUnit::Degree => angle.to_radians(),
+        }
+    }
+
+    /// Convert an angle in radian to this unit
+    pub fn in_unit(self, angle: f64) -> f64 {
+        match self {
+            AngleUnit::Radian => angle,
+            AngleUnit::Degree => angle.to_degrees(),
+        }
+    }
+}
+
+/// Settings of a single [crate::exec::Exec], the ones which are not set are radian and a tolerance of 1e-10.
+/// config.toml is not read yet, so these can only be set here.
+/// Use it with [crate::exec::Exec::from_lex_with], such as `Settings::new().angle_unit(AngleUnit::Degree)`
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Settings {
+    pub(crate) angle_unit: AngleUnit,
+    pub(crate) integral_tolerance: f64,
+}
+
+impl Settings {
+    pub fn new() -> Settings {
+        Settings::default()
+    }
+
+    /// The unit of angles taken by \sin and returned by \arcsin, derivatives of them use it as well
+    pub fn angle_unit(mut self, unit: AngleUnit) -> Settings {
+        self.angle_unit = unit;
+        self
+    }
+
+    pub fn get_angle_unit(&self) -> AngleUnit {
+        self.angle_unit
+    }
+
+    /// How far the value of an integral may be from the exact one, a smaller one takes more steps
+    pub fn integral_tolerance(mut self, tolerance: f64) -> Result<Settings, EvalError> {
+        if !(tolerance.is_finite() && tolerance > 0.0) {
+            return Err(EvalError::DomainError(
+                format!("The tolerance of integrals should be positive, but it is {tolerance}"), Span::default(),
+            ));
+        }
+        self.integral_tolerance = tolerance;
+        Ok(self)
+    }
+
+    pub fn get_integral_tolerance(&self) -> f64 {
+        self.integral_tolerance
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            angle_unit: CONFIG.angle_unit,
+            integral_tolerance: CONFIG.integral_tolerance,
+        }
+    }
 }
 
 fn get_config() -> String {
@@ -30,10 +117,17 @@ lazy_static! {
 
 #[cfg(test)]
 mod tests {
-    use crate::config::get_config;
+    use crate::config::{get_config, AngleUnit, Config};
 
     #[test]
     fn read_config_test() {
         println!("{}", get_config());
+
+        let config: Config = toml::from_str("high_accuracy = false").unwrap();
+        assert_eq!(config.angle_unit, AngleUnit::Radian);
+        assert_eq!(config.integral_tolerance, 1e-10);
+        let config: Config = toml::from_str("high_accuracy = false\nangle_unit = \"degree\"").unwrap();
+        assert_eq!(config.angle_unit, AngleUnit::Degree);
+        assert_eq!(AngleUnit::Degree.to_radian(180.0), std::f64::consts::PI);
     }
 }
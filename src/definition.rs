@@ -0,0 +1,196 @@
+//! [crate::definition] reads variables defined by \var, such as \var{r=2, A=\pi r^2}.
+//! The value of a variable is an expression which may use other variables,
+//! so definitions are sorted to make sure every variable is calculated after the ones it uses
+//!
+use latex_analyzer::ast::{Node, AST};
+use latex_analyzer::lex::{Arg, Lex};
+use latex_analyzer::parser::Parser;
+use latex_analyzer::span::Span;
+use crate::config::AngleUnit;
+use crate::derivative::apply_derivatives;
+use crate::error::EvalError;
+use crate::exec::Exec;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Definition {
+    pub(crate) name: String,
+    pub(crate) node: Node,
+    // other variables defined by \var which node uses
+    pub(crate) dependencies: Vec<String>,
+    pub(crate) span: Span,
+}
+
+/// Read every \var, a variable defined twice takes the last definition.
+/// The definitions are returned in an order where every variable comes after the ones it uses
+pub(crate) fn parse_definitions(vars: &[Arg], unit: AngleUnit) -> Result<Vec<Definition>, EvalError> {
+    let mut definitions: Vec<Definition> = Vec::new();
+    for piece in vars.iter().flat_map(split_definitions) {
+        let definition = parse_definition(&piece)?;
+        definitions.retain(|d| d.name != definition.name);
+        definitions.push(definition);
+    }
+
+    let names: Vec<String> = definitions.iter().map(|d| d.name.clone()).collect();
+    for definition in definitions.iter_mut() {
+        definition.dependencies = definition.node.variables().into_iter()
+            .filter(|v| names.contains(v))
+            .collect();
+    }
+
+    // derivative operators may use the definitions before them, such as B in \var{A=x^2, B=\frac{d}{dx} A}
+    let mut definitions = sort(definitions)?;
+    for i in 0..definitions.len() {
+        definitions[i].node = apply_derivatives(&definitions[i].node, &definitions[..i], unit)?;
+    }
+    Ok(definitions)
+}
+
+/// "a=1, b=2" has two definitions, but the comma in "c=f(1, 2)" does not split anything
+fn split_definitions(arg: &Arg) -> Vec<Arg> {
+    let chars: Vec<char> = arg.value.chars().collect();
+    let mut pieces = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, ch) in chars.iter().enumerate() {
+        match ch {
+            '{' | '(' | '[' => depth += 1,
+            '}' | ')' | ']' => depth -= 1,
+            // "\," is a space, such as the one in 3\,\mathrm{m}
+            ',' if depth == 0 && (i == 0 || chars[i - 1] != '\\') => {
+                pieces.push(sub_arg(arg, &chars, start, i));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    pieces.push(sub_arg(arg, &chars, start, chars.len()));
+
+    // such as the trailing comma of "a=1,"
+    pieces.retain(|p| !p.value.is_empty());
+    pieces
+}
+
+/// chars[start..end] without surrounding spaces, whose span still points into the whole input
+fn sub_arg(arg: &Arg, chars: &[char], mut start: usize, mut end: usize) -> Arg {
+    while start < end && chars[start].is_whitespace() {
+        start += 1;
+    }
+    while end > start && chars[end - 1].is_whitespace() {
+        end -= 1;
+    }
+
+    Arg::new(chars[start..end].iter().collect(), Span::new(start, end).shift(arg.span.start))
+}
+
+fn parse_definition(piece: &Arg) -> Result<Definition, EvalError> {
+    let invalid = || EvalError::InvalidVariable(piece.value.clone(), piece.span);
+
+    let chars: Vec<char> = piece.value.chars().collect();
+    let equal = chars.iter().position(|c| *c == '=').ok_or_else(invalid)?;
+    let name = Exec::var_name(&sub_arg(piece, &chars, 0, equal).value).map_err(|_| invalid())?;
+    let value = sub_arg(piece, &chars, equal + 1, chars.len());
+    if value.value.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut lex = Lex::from_arg(&value);
+    let AST(node, var) = Parser::from_lex(&mut lex)?.parse()?;
+    // \var{a=b\var{b=1}} would be hard to read
+    if let Some(var) = var.first() {
+        return Err(EvalError::InvalidVariable(var.value.clone(), var.span));
+    }
+
+    Ok(Definition {
+        name,
+        node,
+        dependencies: vec![],
+        span: piece.span,
+    })
+}
+
+/// Depth-first topological sort, a variable which uses itself, even through others, can not be calculated
+fn sort(definitions: Vec<Definition>) -> Result<Vec<Definition>, EvalError> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        New,
+        Visiting,
+        Done,
+    }
+
+    fn visit(i: usize, definitions: &[Definition], states: &mut [State], path: &mut Vec<usize>, order: &mut Vec<usize>)
+             -> Result<(), EvalError> {
+        match states[i] {
+            State::Done => return Ok(()),
+            State::Visiting => {
+                let first = path.iter().position(|p| *p == i).unwrap();
+                let cycle = path[first..].iter().chain([&i])
+                    .map(|p| definitions[*p].name.clone())
+                    .collect();
+                // point at the definition which closes the cycle
+                let last = *path.last().unwrap();
+                return Err(EvalError::CircularVariable(cycle, definitions[last].span));
+            }
+            State::New => {}
+        }
+
+        states[i] = State::Visiting;
+        path.push(i);
+        for dependency in definitions[i].dependencies.iter() {
+            let j = definitions.iter().position(|d| d.name == *dependency).unwrap();
+            visit(j, definitions, states, path, order)?;
+        }
+        path.pop();
+        states[i] = State::Done;
+        order.push(i);
+
+        Ok(())
+    }
+
+    let mut states = vec![State::New; definitions.len()];
+    let mut order = Vec::new();
+    for i in 0..definitions.len() {
+        visit(i, &definitions, &mut states, &mut Vec::new(), &mut order)?;
+    }
+
+    let mut definitions: Vec<Option<Definition>> = definitions.into_iter().map(Some).collect();
+    Ok(order.into_iter().map(|i| definitions[i].take().unwrap()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use latex_analyzer::ast::AST;
+    use latex_analyzer::lex::Lex;
+    use latex_analyzer::span::Span;
+    use crate::config::AngleUnit;
+    use crate::definition::parse_definitions;
+    use crate::error::EvalError;
+
+    fn names(s: &str) -> Result<Vec<String>, EvalError> {
+        let ast = Lex::new(s.to_string()).parse().and_then(AST::new)?;
+        Ok(parse_definitions(&ast.1, AngleUnit::Radian)?.into_iter().map(|d| d.name).collect())
+    }
+
+    #[test]
+    fn parse_definitions_test() {
+        assert_eq!(names(r"A\var{A=\pi r^2}\var{r=2}").unwrap(), vec!["r", "A"]);
+        assert_eq!(names(r"x\var{c = a + b, b = 2a, a = 1}").unwrap(), vec!["a", "b", "c"]);
+        assert_eq!(names(r"x\var{a=\frac{1}{2}, T_{0}=a,}").unwrap(), vec!["a", "T_0"]);
+        assert_eq!(names(r"x\var{v=3\,\mathrm{m/s}, t=1}").unwrap(), vec!["v", "t"]);
+        // the later definition takes the priority, so there is no cycle
+        assert_eq!(names(r"x\var{a=b, b=1}\var{a=2}").unwrap(), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn parse_definitions_error_test() {
+        assert_eq!(
+            names(r"x\var{a=b+1, b=c, c=a}").unwrap_err(),
+            EvalError::CircularVariable(vec!["a".into(), "b".into(), "c".into(), "a".into()], Span::new(18, 21)),
+        );
+        assert!(matches!(names(r"x\var{a=a}"), Err(EvalError::CircularVariable(..))));
+        assert_eq!(names(r"x\var{a=1, b}").unwrap_err(), EvalError::InvalidVariable("b".into(), Span::new(11, 12)));
+        assert!(matches!(names(r"x\var{a=}"), Err(EvalError::InvalidVariable(..))));
+        assert!(matches!(names(r"x\var{a+b=1}"), Err(EvalError::InvalidVariable(..))));
+        assert!(matches!(names(r"x\var{a=1+}"), Err(EvalError::Parse(..))));
+    }
+}
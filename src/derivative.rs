@@ -0,0 +1,413 @@
+//! Symbolic differentiation of [Node], the result is a new tree which can be evaluated or printed.
+//! The result is not simplified, so the derivative of x^2 looks like "2x^{2 - 1} \cdot 1"
+//!
+use std::rc::Rc;
+use latex_analyzer::ast::{Node, NodeKind};
+use latex_analyzer::lex::{Arg, Token};
+use latex_analyzer::span::Span;
+use crate::config::AngleUnit;
+use crate::definition::Definition;
+use crate::error::EvalError;
+
+type DerivativeResult = Result<Node, EvalError>;
+
+/// Replace every derivative operator, such as \frac{d}{dx}, \frac{\di{}}{\di{x}} or \frac{\pa{}}{\pa{x}},
+/// with the derivative of what follows it.
+/// An operator applies to the rest of the product, so "\frac{d}{dx} 2x" is the derivative of 2x,
+/// use parentheses if there are several terms, such as "\frac{d}{dx}(x^2 + x)".
+/// Variables defined with x are replaced by their definitions first, see [substitute]
+pub fn apply_derivatives(node: &Node, definitions: &[Definition], unit: AngleUnit) -> DerivativeResult {
+    let apply = |node: &Node| apply_derivatives(node, definitions, unit);
+    if derivative_operator(node).is_some() {
+        return Err(EvalError::Unsupported("Nothing to differentiate".to_string(), node.span));
+    }
+
+    if is_times(node) {
+        let mut factors = Vec::new();
+        flatten_product(node, &mut factors);
+
+        if let Some(i) = factors.iter().position(|f| derivative_operator(f).is_some()) {
+            let x = derivative_operator(factors[i]).unwrap();
+            if i + 1 == factors.len() {
+                return Err(EvalError::Unsupported("Nothing to differentiate".to_string(), factors[i].span));
+            }
+
+            let operand = apply(&product(&factors[i + 1..]))?;
+            let result = derivative(&substitute(&operand, &x, definitions), &x, unit)?;
+            return match i {
+                0 => Ok(result),
+                _ => Ok(op(Token::Times, apply(&product(&factors[..i]))?, result, node.span)),
+            };
+        }
+    }
+
+    let mut result = node.clone();
+    result.left = node.left.as_ref().map(|l| apply(l).map(Rc::new)).transpose()?;
+    result.right = node.right.as_ref().map(|r| apply(r).map(Rc::new)).transpose()?;
+    result.optional_args = node.optional_args.iter().map(apply).collect::<Result<_, _>>()?;
+    result.required_args = node.required_args.iter().map(apply).collect::<Result<_, _>>()?;
+
+    Ok(result)
+}
+
+/// Replace variables defined by \var with their definitions if they depend on x, even through other variables,
+/// so A in \frac{d}{dx} A\var{A=x^2} is not taken as a constant
+pub(crate) fn substitute(node: &Node, x: &str, definitions: &[Definition]) -> Node {
+    if let Some(name) = name_of(node).filter(|name| *name != x) {
+        return match definitions.iter().find(|d| d.name == name) {
+            Some(definition) => {
+                let value = substitute(&definition.node, x, definitions);
+                if depends_on(&value, x) { value } else { node.clone() }
+            }
+            None => node.clone(),
+        };
+    }
+
+    let mut result = node.clone();
+    result.left = node.left.as_ref().map(|l| Rc::new(substitute(l, x, definitions)));
+    result.right = node.right.as_ref().map(|r| Rc::new(substitute(r, x, definitions)));
+    result.optional_args = node.optional_args.iter().map(|a| substitute(a, x, definitions)).collect();
+    result.required_args = node.required_args.iter().map(|a| substitute(a, x, definitions)).collect();
+    result
+}
+
+/// The derivative of node with respect to the variable named x
+pub fn derivative(node: &Node, x: &str, unit: AngleUnit) -> DerivativeResult {
+    let span = node.span;
+    if !depends_on(node, x) {
+        return Ok(num("0", span));
+    }
+
+    match node.node_kind {
+        NodeKind::Num => match &node.value {
+            // the only expression which depends on x is x itself
+            Some(Token::Expression(_)) => Ok(num("1", span)),
+            Some(Token::Function(fun, _, _)) => function_derivative(fun, node, x, unit),
+            _ => Err(EvalError::Unsupported(format!("Can not differentiate {:?}", node.value), span)),
+        },
+        NodeKind::Unary => {
+            let operand = derivative(node.right.as_ref().unwrap(), x, unit)?;
+            Ok(unary(node.op.clone().unwrap(), operand, span))
+        }
+        NodeKind::Equation => Err(EvalError::Unsupported("Can not differentiate an equation".to_string(), span)),
+        NodeKind::Op => {
+            let u = node.left.as_ref().unwrap();
+            let v = node.right.as_ref().unwrap();
+
+            match node.op {
+                Some(Token::Add) | Some(Token::Sub) | Some(Token::PlusMinus) => {
+                    Ok(op(node.op.clone().unwrap(), derivative(u, x, unit)?, derivative(v, x, unit)?, span))
+                }
+                // (uv)' = u'v + uv'
+                Some(Token::Times) => Ok(op(
+                    Token::Add,
+                    op(Token::Times, derivative(u, x, unit)?, (**v).clone(), span),
+                    op(Token::Times, (**u).clone(), derivative(v, x, unit)?, span),
+                    span,
+                )),
+                Some(Token::Div) => quotient(u, v, x, unit, span),
+                Some(Token::Superscript(_)) => power(u, v, x, unit, span),
+                _ => Err(EvalError::Unsupported(format!("Can not differentiate operator {:?}", node.op), span)),
+            }
+        }
+    }
+}
+
+/// The derivative of built-in functions
+fn function_derivative(fun: &str, node: &Node, x: &str, unit: AngleUnit) -> DerivativeResult {
+    let span = node.span;
+    let required = |i: usize| {
+        node.required_args.get(i)
+            .ok_or_else(|| EvalError::WrongArity(fun.to_string(), i + 1, node.required_args.len(), span))
+    };
+
+    match fun {
+        "frac" => quotient(required(0)?, required(1)?, x, unit, span),
+        // \sqrt[n]{u} = u^{\frac{1}{n}}
+        "sqrt" => {
+            let n = node.optional_args.first().cloned().unwrap_or_else(|| num("2", span));
+            power(required(0)?, &op(Token::Div, num("1", span), n, span), x, unit, span)
+        }
+        // (\ln u)' = \frac{u'}{u}
+        "ln" => {
+            let u = required(0)?;
+            Ok(op(Token::Div, derivative(u, x, unit)?, u.clone(), span))
+        }
+        // (\log_b u)' = \frac{u'}{u \ln b}, and \frac{\ln u}{\ln b} if b depends on x as well
+        "log" => {
+            let u = required(0)?;
+            let base = node.optional_args.first().cloned().unwrap_or_else(|| num("10", span));
+            if depends_on(&base, x) {
+                let (ln_u, ln_b) = (function("ln", vec![u.clone()], span), function("ln", vec![base], span));
+                return quotient(&ln_u, &ln_b, x, unit, span);
+            }
+            let denominator = op(Token::Times, u.clone(), function("ln", vec![base], span), span);
+            Ok(op(Token::Div, derivative(u, x, unit)?, denominator, span))
+        }
+        "exp" | "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "sinh" | "cosh" | "tanh" | "coth" => {
+            let u = required(0)?;
+            let f = |name: &str| function(name, vec![u.clone()], span);
+            let times = |a: Node, b: Node| op(Token::Times, a, b, span);
+            let square = |a: Node| superscript(a, num("2", span), span);
+            let neg = |a: Node| unary(Token::Sub, a, span);
+            let one_minus = |a: Node| op(Token::Sub, num("1", span), a, span);
+
+            let outer = match fun {
+                "exp" => f("exp"),
+                "sin" => f("cos"),
+                "cos" => neg(f("sin")),
+                "tan" => square(f("sec")),
+                "cot" => neg(square(f("csc"))),
+                "sec" => times(f("sec"), f("tan")),
+                "csc" => neg(times(f("csc"), f("cot"))),
+                "sinh" => f("cosh"),
+                "cosh" => f("sinh"),
+                "tanh" => one_minus(square(f("tanh"))),
+                _ => one_minus(square(f("coth"))),
+            };
+            // trigonometric functions take angles in the configured unit, so d\sin{x} = \cos{x} \frac{\pi}{180} dx in degree
+            let outer = match angle_factor(unit, span) {
+                Some(k) if ["sin", "cos", "tan", "cot", "sec", "csc"].contains(&fun) => times(outer, k),
+                _ => outer,
+            };
+            Ok(times(outer, derivative(u, x, unit)?))
+        }
+        "arcsin" | "arccos" | "arctan" | "arsinh" | "arcosh" | "artanh" => {
+            let u = required(0)?;
+            let square = superscript(u.clone(), num("2", span), span);
+            let sqrt = |a: Node| function("sqrt", vec![a], span);
+
+            let denominator = match fun {
+                "arcsin" | "arccos" => sqrt(op(Token::Sub, num("1", span), square, span)),
+                "arctan" => op(Token::Add, num("1", span), square, span),
+                "arsinh" => sqrt(op(Token::Add, square, num("1", span), span)),
+                "arcosh" => sqrt(op(Token::Sub, square, num("1", span), span)),
+                _ => op(Token::Sub, num("1", span), square, span),
+            };
+            // inverse trigonometric functions give angles in the configured unit
+            let denominator = match angle_factor(unit, span) {
+                Some(k) if ["arcsin", "arccos", "arctan"].contains(&fun) => op(Token::Times, denominator, k, span),
+                _ => denominator,
+            };
+            let result = op(Token::Div, derivative(u, x, unit)?, denominator, span);
+            Ok(if fun == "arccos" { unary(Token::Sub, result, span) } else { result })
+        }
+        _ => Err(EvalError::Unsupported(format!("Can not differentiate function {fun}"), span)),
+    }
+}
+
+/// How many radians an angle of 1 is, None for radian itself
+fn angle_factor(unit: AngleUnit, span: Span) -> Option<Node> {
+    match unit {
+        AngleUnit::Radian => None,
+        AngleUnit::Degree => Some(op(Token::Div, num("\\pi", span), num("180", span), span)),
+    }
+}
+
+/// (\frac{u}{v})' = \frac{u'v - uv'}{v^2}
+fn quotient(u: &Node, v: &Node, x: &str, unit: AngleUnit, span: Span) -> DerivativeResult {
+    let numerator = op(
+        Token::Sub,
+        op(Token::Times, derivative(u, x, unit)?, v.clone(), span),
+        op(Token::Times, u.clone(), derivative(v, x, unit)?, span),
+        span,
+    );
+    Ok(op(Token::Div, numerator, superscript(v.clone(), num("2", span), span), span))
+}
+
+/// The derivative of u^v, there are three cases:
+/// x^n, n^x and u^v where both u and v depend on x
+fn power(u: &Node, v: &Node, x: &str, unit: AngleUnit, span: Span) -> DerivativeResult {
+    let u_prime = derivative(u, x, unit)?;
+    let v_prime = derivative(v, x, unit)?;
+    let ln = |u: &Node| function("ln", vec![u.clone()], span);
+
+    let result = match (depends_on(u, x), depends_on(v, x)) {
+        // (u^n)' = n u^{n - 1} u'
+        (_, false) => op(
+            Token::Times,
+            op(Token::Times, v.clone(), superscript(u.clone(), op(Token::Sub, v.clone(), num("1", span), span), span), span),
+            u_prime,
+            span,
+        ),
+        // (n^v)' = n^v \ln{n} v'
+        (false, true) => op(
+            Token::Times,
+            op(Token::Times, superscript(u.clone(), v.clone(), span), ln(u), span),
+            v_prime,
+            span,
+        ),
+        // (u^v)' = u^v (v' \ln{u} + \frac{v u'}{u})
+        (true, true) => op(
+            Token::Times,
+            superscript(u.clone(), v.clone(), span),
+            op(
+                Token::Add,
+                op(Token::Times, v_prime, ln(u), span),
+                op(Token::Div, op(Token::Times, v.clone(), u_prime, span), u.clone(), span),
+                span,
+            ),
+            span,
+        ),
+    };
+
+    Ok(result)
+}
+
+/// The name of variable if node is a derivative operator,
+/// such as "x" for \frac{d}{dx}, \frac{\di{}}{\di{x}} or \frac{\pa{}}{\pa{x}}
+fn derivative_operator(node: &Node) -> Option<String> {
+    let Some(Token::Function(fun, _, _)) = &node.value else {
+        return None;
+    };
+    let [numerator, denominator] = node.required_args.as_slice() else {
+        return None;
+    };
+    if fun != "frac" || !matches!(name_of(numerator), Some("d" | "\\di" | "\\pa" | "\\partial")) {
+        return None;
+    }
+
+    match (&denominator.value, denominator.required_args.as_slice()) {
+        // \di{x} or \pa{x}
+        (Some(Token::Function(f, _, _)), [x]) if f == "di" || f == "pa" => name_of(x).map(str::to_string),
+        // dx or \partial x
+        _ if is_times(denominator) => {
+            let d = name_of(denominator.left.as_ref().unwrap());
+            let x = name_of(denominator.right.as_ref().unwrap());
+            match (d, x) {
+                (Some(d), Some(x)) if Some(d) == name_of(numerator) => Some(x.to_string()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The name if node is a single identifier
+fn name_of(node: &Node) -> Option<&str> {
+    match &node.value {
+        Some(Token::Expression(e)) if !e.starts_with(|c: char| c.is_ascii_digit()) => Some(e),
+        _ => None,
+    }
+}
+
+fn depends_on(node: &Node, x: &str) -> bool {
+    name_of(node) == Some(x)
+        || node.left.as_ref().is_some_and(|l| depends_on(l, x))
+        || node.right.as_ref().is_some_and(|r| depends_on(r, x))
+        || node.optional_args.iter().chain(node.required_args.iter()).any(|a| depends_on(a, x))
+}
+
+fn is_times(node: &Node) -> bool {
+    node.node_kind == NodeKind::Op && node.op == Some(Token::Times)
+}
+
+/// "a \cdot b \cdot c" is ((a \cdot b) \cdot c), so the factors are [a, b, c]
+pub(crate) fn flatten_product<'a>(node: &'a Node, factors: &mut Vec<&'a Node>) {
+    if is_times(node) {
+        flatten_product(node.left.as_ref().unwrap(), factors);
+        flatten_product(node.right.as_ref().unwrap(), factors);
+    } else {
+        factors.push(node);
+    }
+}
+
+pub(crate) fn product(factors: &[&Node]) -> Node {
+    let first = factors[0].clone();
+    factors[1..].iter().fold(first, |acc, f| {
+        let span = acc.span.merge(f.span);
+        op(Token::Times, acc, (*f).clone(), span)
+    })
+}
+
+pub(crate) fn num(value: &str, span: Span) -> Node {
+    Node {
+        node_kind: NodeKind::Num,
+        value: Some(Token::Expression(value.to_string())),
+        op: None,
+        left: None,
+        right: None,
+        optional_args: vec![],
+        required_args: vec![],
+        span,
+    }
+}
+
+fn function(name: &str, required_args: Vec<Node>, span: Span) -> Node {
+    let raw = required_args.iter().map(|a| Arg::new(a.to_latex(), a.span)).collect();
+    Node {
+        value: Some(Token::Function(name.to_string(), vec![], raw)),
+        required_args,
+        ..num("", span)
+    }
+}
+
+pub(crate) fn op(op: Token, left: Node, right: Node, span: Span) -> Node {
+    Node {
+        node_kind: NodeKind::Op,
+        value: None,
+        op: Some(op),
+        left: Some(Rc::new(left)),
+        right: Some(Rc::new(right)),
+        optional_args: vec![],
+        required_args: vec![],
+        span,
+    }
+}
+
+pub(crate) fn unary(op: Token, operand: Node, span: Span) -> Node {
+    Node {
+        node_kind: NodeKind::Unary,
+        value: None,
+        op: Some(op),
+        left: None,
+        right: Some(Rc::new(operand)),
+        optional_args: vec![],
+        required_args: vec![],
+        span,
+    }
+}
+
+pub(crate) fn superscript(base: Node, exponent: Node, span: Span) -> Node {
+    op(Token::Superscript(Arg::new(String::new(), span)), base, exponent, span)
+}
+
+#[cfg(test)]
+mod tests {
+    use latex_analyzer::ast::AST;
+    use latex_analyzer::lex::Lex;
+    use crate::config::AngleUnit;
+    use crate::derivative::{apply_derivatives, derivative};
+
+    fn tree(s: &str) -> latex_analyzer::ast::Node {
+        AST::new(Lex::new(s.to_string()).parse().unwrap()).unwrap().0
+    }
+
+    #[test]
+    fn derivative_test() {
+        assert_eq!(derivative(&tree("3"), "x", AngleUnit::Radian).unwrap().to_latex(), "0");
+        assert_eq!(derivative(&tree("x + y"), "x", AngleUnit::Radian).unwrap().to_latex(), "1 + 0");
+        assert_eq!(derivative(&tree("x^2"), "x", AngleUnit::Radian).unwrap().to_latex(), r"2x^{2 - 1} \cdot 1");
+        assert_eq!(derivative(&tree(r"\ln{x}"), "x", AngleUnit::Radian).unwrap().to_latex(), r"\frac{1}{x}");
+        assert_eq!(derivative(&tree(r"\sin{x}"), "x", AngleUnit::Radian).unwrap().to_latex(), r"\cos{x} \cdot 1");
+        assert_eq!(derivative(&tree(r"\arccos{x}"), "x", AngleUnit::Radian).unwrap().to_latex(), r"-\frac{1}{\sqrt{1 - x^{2}}}");
+        assert_eq!(
+            derivative(&tree(r"\sin{x}"), "x", AngleUnit::Degree).unwrap().to_latex(),
+            r"\cos{x} \cdot \frac{\pi}{180} \cdot 1",
+        );
+        assert!(derivative(&tree("x = 1"), "x", AngleUnit::Radian).is_err());
+        assert!(derivative(&tree(r"\int_0^x{y}"), "x", AngleUnit::Radian).is_err());
+    }
+
+    #[test]
+    fn apply_derivatives_test() {
+        let apply = |s: &str| apply_derivatives(&tree(s), &[], AngleUnit::Radian).map(|n| n.to_latex());
+
+        assert_eq!(apply(r"\frac{d}{dx} x").unwrap(), "1");
+        assert_eq!(apply(r"\frac{\di{}}{\di{x}} 2x").unwrap(), r"0x + 2 \cdot 1");
+        assert_eq!(apply(r"3\frac{\pa{}}{\pa{y}} x").unwrap(), r"3 \cdot 0");
+        assert_eq!(apply(r"\frac{\partial}{\partial x} (x + 1)").unwrap(), "1 + 0");
+        assert!(apply(r"\frac{d}{dx}").is_err());
+        assert!(apply(r"1 + \frac{d}{dx}").is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! [crate::error] describes what can go wrong when an expression is calculated,
+//! so callers can tell a typo in the input from a division by zero
+//!
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use latex_analyzer::error::ParseError;
+use latex_analyzer::span::Span;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum EvalError {
+    // the LaTeX input can not be turned into an AST
+    Parse(ParseError),
+    // a variable which is neither defined by \var nor a built-in constant
+    UndefinedVariable(String, Span),
+    // a function which is neither built-in nor registered
+    UnknownFunction(String, Span),
+    // a function which can not be registered with its name, such as \sum, or \ln without override_build_in
+    InvalidRegistration(String, Span),
+    // function name, how many arguments it expects and how many it gets
+    WrongArity(String, usize, usize, Span),
+    // the operands are out of the domain, such as division by zero or an even root of a negative number
+    DomainError(String, Span),
+    // a result which is too large to be a number, such as \exp{1000}
+    Overflow(String, Span),
+    // a \var which can not be read, such as \var{a}
+    InvalidVariable(String, Span),
+    // variables defined by \var which use each other, such as ["a", "b", "a"] for \var{a=b, b=a}
+    CircularVariable(Vec<String>, Span),
+    // a unit which can not be read, such as "m^{x}", or "furlong" given to [crate::unit::Quantity::unit]
+    UnknownUnit(String, Span),
+    // quantities whose units do not match, such as 1\,\mathrm{m} + 1\,\mathrm{s}
+    DimensionError(String, Span),
+    // a string which is not a number
+    InvalidNumber(String, Span),
+    // an equation without any root near the guess
+    NoSolution(String, Span),
+    // something we can not do with this expression, such as calculating an equation
+    Unsupported(String, Span),
+}
+
+impl EvalError {
+    pub fn span(&self) -> Span {
+        match self {
+            EvalError::Parse(e) => e.span(),
+            EvalError::UndefinedVariable(_, span)
+            | EvalError::UnknownFunction(_, span)
+            | EvalError::InvalidRegistration(_, span)
+            | EvalError::WrongArity(_, _, _, span)
+            | EvalError::DomainError(_, span)
+            | EvalError::Overflow(_, span)
+            | EvalError::InvalidVariable(_, span)
+            | EvalError::CircularVariable(_, span)
+            | EvalError::UnknownUnit(_, span)
+            | EvalError::DimensionError(_, span)
+            | EvalError::InvalidNumber(_, span)
+            | EvalError::NoSolution(_, span)
+            | EvalError::Unsupported(_, span) => *span,
+        }
+    }
+
+    /// Functions do not know where they are called, so the caller tells the error
+    pub(crate) fn at(self, span: Span) -> Self {
+        match self {
+            EvalError::UndefinedVariable(s, _) => EvalError::UndefinedVariable(s, span),
+            EvalError::UnknownFunction(s, _) => EvalError::UnknownFunction(s, span),
+            EvalError::InvalidRegistration(s, _) => EvalError::InvalidRegistration(s, span),
+            EvalError::WrongArity(s, expected, found, _) => EvalError::WrongArity(s, expected, found, span),
+            EvalError::DomainError(s, _) => EvalError::DomainError(s, span),
+            EvalError::Overflow(s, _) => EvalError::Overflow(s, span),
+            EvalError::InvalidVariable(s, _) => EvalError::InvalidVariable(s, span),
+            EvalError::CircularVariable(names, _) => EvalError::CircularVariable(names, span),
+            EvalError::UnknownUnit(s, _) => EvalError::UnknownUnit(s, span),
+            EvalError::DimensionError(s, _) => EvalError::DimensionError(s, span),
+            EvalError::InvalidNumber(s, _) => EvalError::InvalidNumber(s, span),
+            EvalError::NoSolution(s, _) => EvalError::NoSolution(s, span),
+            EvalError::Unsupported(s, _) => EvalError::Unsupported(s, span),
+            e => e,
+        }
+    }
+
+    /// The message with the line of source which goes wrong, see [Span::render]
+    pub fn render(&self, source: &str) -> String {
+        format!("{self}\n{}", self.span().render(source))
+    }
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::Parse(e) => write!(f, "{e}"),
+            EvalError::UndefinedVariable(name, _) => write!(f, "Can not get variable {name}"),
+            EvalError::UnknownFunction(name, _) => write!(f, "Can`t get the function: {name}"),
+            EvalError::InvalidRegistration(message, _) => write!(f, "{message}"),
+            EvalError::WrongArity(name, expected, found, _) => {
+                write!(f, "Function {name} needs {expected} args, but gets {found}")
+            }
+            EvalError::DomainError(message, _) => write!(f, "{message}"),
+            EvalError::Overflow(message, _) => write!(f, "{message}"),
+            EvalError::InvalidVariable(var, _) => write!(f, "Can not read variable {var}"),
+            EvalError::CircularVariable(names, _) => {
+                write!(f, "Variables depend on each other: {}", names.join(" -> "))
+            }
+            EvalError::UnknownUnit(unit, _) => write!(f, "Can not read unit {unit}"),
+            EvalError::DimensionError(message, _) => write!(f, "{message}"),
+            EvalError::InvalidNumber(s, _) => write!(f, "{s} is not a number"),
+            EvalError::NoSolution(unknown, _) => write!(f, "Can not find {unknown} which makes the equation hold"),
+            EvalError::Unsupported(message, _) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl Error for EvalError {}
+
+impl From<ParseError> for EvalError {
+    fn from(e: ParseError) -> Self {
+        EvalError::Parse(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use latex_analyzer::error::ParseError;
+    use latex_analyzer::span::Span;
+    use crate::error::EvalError;
+
+    #[test]
+    fn render_test() {
+        let e = EvalError::UndefinedVariable("x".to_string(), Span::default()).at(Span::new(4, 5));
+        assert_eq!(e.render("a + x"), "Can not get variable x\n1 | a + x\n  |     ^");
+
+        let e = EvalError::from(ParseError::UnknownChar('?', Span::new(0, 1)));
+        assert_eq!(e.span(), Span::new(0, 1));
+    }
+}
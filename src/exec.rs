@@ -1,118 +1,591 @@
+use std::cell::OnceCell;
 use std::collections::HashMap;
-use crate::function::get_function;
-use crate::transformer::{string_to_known, strings_to_known};
+use crate::buildin_function::BUILD_IN_CONSTANT;
+use crate::compile::Program;
+use crate::definition::{parse_definitions, Definition};
+use crate::derivative::{apply_derivatives, derivative, substitute};
+use crate::error::EvalError;
+use crate::config::Settings;
+use crate::integral::{integrate, split_differential, Integral};
+use crate::simplify::simplify;
+use crate::function::FunctionRegistry;
+use crate::known::Known;
+use crate::transformer::string_to_known;
+use crate::unit::{function_dimension, Dimension, Quantity};
 use latex_analyzer::ast::{Node, NodeKind, AST};
 use latex_analyzer::lex::{Lex, Token};
-use latex_analyzer::parser::Parser;
-use num::pow;
-use crate::config;
+use latex_analyzer::span::{Span, Spanned};
 
 type VarMap = HashMap<String, f64>;
+type QuantityMap = HashMap<String, Quantity>;
+
+// \sum and \prod with more terms are given up, they would take too long to calculate
+const MAX_TERMS: i64 = 10_000_000;
+// 2^53, larger integers can not be told apart as f64
+const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// Values of variables given by the caller, which override the ones defined by \var.
+/// Names are read like LaTeX, so "T_{0}" and "T_0" are the same variable.
+/// A value is in SI base units, and takes the unit of the \var it overrides
+#[derive(Default, Clone, Debug)]
+pub struct Bindings {
+    vars: VarMap,
+}
+
+impl Bindings {
+    pub fn new() -> Bindings {
+        Bindings::default()
+    }
+
+    /// Set or override a variable, such as `Bindings::new().set("a", 1.0)?.set("b", 2.0)?`
+    pub fn set(mut self, name: &str, value: f64) -> Result<Bindings, EvalError> {
+        self.vars.insert(Exec::var_name(name)?, value);
+        Ok(self)
+    }
+
+    pub(crate) fn vars(&self) -> &VarMap {
+        &self.vars
+    }
+
+    pub fn get(&self, name: &str) -> Option<f64> {
+        let name = Exec::var_name(name).ok()?;
+        self.vars.get(&name).copied()
+    }
+}
+
+/// Columns of data for [Exec::calculate_columns], the i-th result is calculated with the i-th value of every column.
+/// Variables which are not in columns are taken from the scalar bindings or \var, and used for every row
+#[derive(Default, Clone, Debug)]
+pub struct Columns<'a> {
+    columns: Vec<(String, &'a [f64])>,
+    scalars: Bindings,
+}
+
+impl<'a> Columns<'a> {
+    pub fn new() -> Columns<'a> {
+        Columns::default()
+    }
+
+    /// Set or override a column, all columns must have the same length
+    pub fn column(mut self, name: &str, values: &'a [f64]) -> Result<Columns<'a>, EvalError> {
+        let name = Exec::var_name(name)?;
+        self.columns.retain(|(n, _)| *n != name);
+        self.columns.push((name, values));
+        Ok(self)
+    }
+
+    /// Set or override a variable which is the same for every row
+    pub fn scalar(mut self, name: &str, value: f64) -> Result<Columns<'a>, EvalError> {
+        self.scalars = self.scalars.set(name, value)?;
+        Ok(self)
+    }
+
+    pub(crate) fn columns(&self) -> &[(String, &'a [f64])] {
+        &self.columns
+    }
+
+    pub(crate) fn scalars(&self) -> &Bindings {
+        &self.scalars
+    }
+
+    /// How many rows there are, a Columns without any column has one row of scalars
+    pub fn len(&self) -> Result<usize, EvalError> {
+        let Some((first, values)) = self.columns.first() else {
+            return Ok(1);
+        };
+        match self.columns.iter().find(|(_, v)| v.len() != values.len()) {
+            Some((name, v)) => Err(EvalError::Unsupported(
+                format!("Column {first} has {} values, but column {name} has {}", values.len(), v.len()),
+                Span::default(),
+            )),
+            None => Ok(values.len()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == Ok(0)
+    }
+}
 
 pub struct Exec {
     node: Node,
-    var_map: VarMap,
+    // values of variables defined by \var, which are calculated when they are first used, see [Exec::var_map]
+    var_map: OnceCell<QuantityMap>,
+    // definitions from \var in the order they can be calculated, used when some of the variables are bound
+    definitions: Vec<Definition>,
+    // the LaTeX input, use [EvalError::render] with it to show where an error occurs
+    source: String,
+    // functions only this expression uses, see [Exec::functions]
+    functions: FunctionRegistry,
+    settings: Settings,
 }
 
 impl Exec {
-    pub fn from_lex(mut lex: Lex) -> Exec {
-        let parser = Parser::from_proto(lex.parse());
-        let ast = AST::new(parser.to_postfix_proto());
+    pub fn from_lex(lex: Lex) -> Result<Exec, EvalError> {
+        Exec::from_lex_with(lex, Settings::new())
+    }
+
+    /// Use settings instead of the config, such as angles in degree
+    pub fn from_lex_with(mut lex: Lex, settings: Settings) -> Result<Exec, EvalError> {
+        let source = lex.source();
+        let ast = lex.parse().and_then(AST::new)?;
+        let definitions = parse_definitions(&ast.1, settings.angle_unit)?;
+        // derivative operators, such as \frac{d}{dx}, are calculated exactly rather than numerically
+        let node = apply_derivatives(&ast.0, &definitions, settings.angle_unit)?;
+
+        let mut functions = FunctionRegistry::new();
+        functions.set_angle_unit(settings.angle_unit);
+        // definitions are only sorted here, a definition may use a variable which is bound later
+        Ok(Exec {
+            node,
+            var_map: OnceCell::new(),
+            definitions,
+            source,
+            functions,
+            settings,
+        })
+    }
 
+    /// The LaTeX input, which errors point into
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The derivative with respect to x, which is evaluated with the same variables
+    pub fn derivative(&self, x: &str) -> Result<Exec, EvalError> {
+        let name = Exec::var_name(x)?;
+        let node = derivative(&substitute(&self.node, &name, &self.definitions), &name, self.settings.angle_unit)?;
+
+        Ok(Exec {
+            node,
+            var_map: self.var_map.clone(),
+            definitions: self.definitions.clone(),
+            source: self.source.clone(),
+            functions: self.functions.clone(),
+            settings: self.settings,
+        })
+    }
+
+    /// Fold constants and combine like terms, so the result is cheaper to calculate and easier to read
+    pub fn simplify(&self) -> Exec {
         Exec {
-            node: ast.0,
-            var_map: Exec::parse_var(&ast.1),
+            node: simplify(&self.node),
+            var_map: self.var_map.clone(),
+            definitions: self.definitions.clone(),
+            source: self.source.clone(),
+            functions: self.functions.clone(),
+            settings: self.settings,
         }
     }
 
-    fn parse_var(vars: &Vec<String>) -> VarMap {
-        let mut var_map = VarMap::new();
+    pub fn settings(&self) -> Settings {
+        self.settings
+    }
 
-        for var in vars.iter() {
-            let s = var.replace(" ", "");
-            let mut split = s.split("=");
-            let (name, value) = (split.next().unwrap(), split.next().unwrap());
-            var_map.insert(name.to_string(), string_to_known(&value.to_string()).unwrap().get_value());
-        }
+    /// Functions registered for this expression, which take the priority of [FunctionRegistry::global]
+    pub fn functions(&self) -> &FunctionRegistry {
+        &self.functions
+    }
 
-        var_map
+    /// Compile the expression to a [Program], which is much faster to calculate many times.
+    /// Functions are looked up when compiling, so registering them later does not change the program
+    pub fn compile(&self) -> Result<Program, EvalError> {
+        Program::new(&self.node, self.var_map(), &self.definitions, &self.functions)
     }
 
-    pub fn calculate(&self) -> Result<f64, String> {
-        self.evaluate_node(&self.node)
+    /// The expression which is calculated, as LaTeX
+    pub fn to_latex(&self) -> String {
+        self.node.to_latex()
     }
 
-    fn evaluate_node(&self, node: &Node) -> Result<f64, String> {
-        return if node.node_kind == NodeKind::Num {
-            let Some(ref value) = node.value.as_ref() else {
-                return Err(format!("Node {node:?} can not get value"));
-            };
-            match value {
-                Token::Function(fun, op, re) => {
-                    let fun = get_function(fun)?;
-                    let op = strings_to_known(op);
-                    let re = strings_to_known(re);
-                    let result = (fun.calc)(op, re);
-
-                    Ok(result.unwrap())
+    /// Values of definitions which can be calculated without bound variables.
+    /// A definition which can not, such as A in \var{A=\pi r^2} without r, is left to [Exec::derive]
+    fn var_map(&self) -> &QuantityMap {
+        self.var_map.get_or_init(|| {
+            let mut var_map = QuantityMap::new();
+            for definition in self.definitions.iter() {
+                if let Ok(value) = self.evaluate_node(&definition.node, &[&var_map]) {
+                    var_map.insert(definition.name.clone(), value);
                 }
-                Token::Expression(expr) => {
-                    return match string_to_known(expr) {
-                        Some(f) => Ok(f.get_value()),
-                        None => match self.var_map.get(expr) {
-                            Some(f) => Ok(f.clone()),
-                            None => Err(format!("Can not get variable {expr}")),
+            }
+            var_map
+        })
+    }
+
+    /// Values of definitions which change because of bound variables or are not in var_map, the others stay the same.
+    /// Definitions which are bound themselves are skipped, since the bound value takes the priority
+    fn derive(&self, bound: &QuantityMap) -> Result<QuantityMap, EvalError> {
+        let mut derived = QuantityMap::new();
+        for definition in self.definitions.iter() {
+            if bound.contains_key(&definition.name) {
+                continue;
+            }
+            let changed = definition.dependencies.iter()
+                .any(|d| bound.contains_key(d) || derived.contains_key(d));
+            if changed || !self.var_map().contains_key(&definition.name) {
+                let value = self.evaluate_node(&definition.node, &[bound, &derived, self.var_map()])?;
+                derived.insert(definition.name.clone(), value);
+            }
+        }
+
+        Ok(derived)
+    }
+
+    /// Evaluate node with bound variables, \var gives the others
+    fn evaluate_with(&self, node: &Node, bound: &QuantityMap) -> Result<Quantity, EvalError> {
+        let derived = self.derive(bound)?;
+        self.evaluate_node(node, &[bound, &derived, self.var_map()])
+    }
+
+    /// Bound values take the units of the \var they override
+    fn bind(&self, vars: &VarMap) -> QuantityMap {
+        vars.iter()
+            .map(|(name, value)| {
+                let dimension = self.var_map().get(name).map(|q| q.dimension).unwrap_or_default();
+                (name.clone(), Quantity { value: *value, dimension })
+            })
+            .collect()
+    }
+
+    /// Lex the name of a variable, so that "T_{0}" in \var is the same variable as "T_0" in expression.
+    /// A name is only valid if it is a single identifier
+    pub(crate) fn var_name(name: &str) -> Result<String, EvalError> {
+        let invalid = || EvalError::InvalidVariable(name.to_string(), Span::default());
+
+        let proto = Lex::new(name.to_string()).parse().map_err(|_| invalid())?;
+        match proto.as_slice() {
+            [Spanned { value: Token::Expression(name), .. }, Spanned { value: Token::Eos, .. }]
+            if string_to_known(name).is_err() => Ok(name.clone()),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// The result in SI base units, use [Exec::calculate_quantity] to get its unit as well
+    pub fn calculate(&self) -> Result<f64, EvalError> {
+        Ok(self.calculate_quantity()?.value)
+    }
+
+    /// The result with its unit, such as "2\,\mathrm{m} \cdot 3\,\mathrm{m}" gives 6 m^2.
+    /// Units which do not match, such as 1\,\mathrm{m} + 1\,\mathrm{s}, are errors
+    pub fn calculate_quantity(&self) -> Result<Quantity, EvalError> {
+        if self.node.node_kind == NodeKind::Equation {
+            return Err(EvalError::Unsupported(
+                "Can not calculate an equation, try holds or solve_for".to_string(), self.node.span,
+            ));
+        }
+        self.evaluate_with(&self.node, &QuantityMap::new())
+    }
+
+    /// Calculate an integral with the estimate of its error, such as \int_0^1 x^2 \di{x}.
+    /// The value is in SI base units, just like [Exec::calculate]
+    pub fn calculate_integral(&self) -> Result<Integral, EvalError> {
+        match &self.node.value {
+            Some(Token::Function(name, _, _)) if name == "int" => {
+                let derived = self.derive(&QuantityMap::new())?;
+                Ok(self.evaluate_integral(&self.node, &[&derived, self.var_map()])?.0)
+            }
+            _ => Err(EvalError::Unsupported("This is not an integral".to_string(), self.node.span)),
+        }
+    }
+
+    /// Calculate with variables from bindings, and \var gives the ones which are not bound.
+    /// A variable defined by \var with bound ones is calculated again, such as A in \var{A=\pi r^2} when r is bound.
+    /// The formula is only parsed once, so it is cheap to call this with many bindings
+    pub fn calculate_with(&self, bindings: &Bindings) -> Result<f64, EvalError> {
+        if self.node.node_kind == NodeKind::Equation {
+            return Err(EvalError::Unsupported(
+                "Can not calculate an equation, try holds or solve_for".to_string(), self.node.span,
+            ));
+        }
+        Ok(self.evaluate_with(&self.node, &self.bind(&bindings.vars))?.value)
+    }
+
+    /// Calculate once for every row of columns, see [Columns].
+    /// The expression is compiled only once, so this is much faster than calling [Exec::calculate_with] in a loop.
+    /// An expression which can not be compiled, such as one with \sum, is calculated row by row instead
+    pub fn calculate_columns(&self, columns: &Columns) -> Result<Vec<f64>, EvalError> {
+        match self.compile() {
+            Ok(program) => program.calculate_columns(columns),
+            Err(EvalError::Unsupported(..)) => {
+                let rows = columns.len()?;
+                let mut bindings = columns.scalars().clone();
+                (0..rows)
+                    .map(|row| {
+                        for (name, values) in columns.columns() {
+                            bindings.vars.insert(name.clone(), values[row]);
                         }
-                    };
+                        self.calculate_with(&bindings)
+                    })
+                    .collect()
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Calculate every result of an expression with "\\pm", such as "a \\pm b" gives [a + b, a - b].
+    /// An expression without "\\pm" has only one result
+    pub fn calculate_all(&self) -> Result<Vec<f64>, EvalError> {
+        if self.node.node_kind == NodeKind::Equation {
+            return Err(EvalError::Unsupported(
+                "Can not calculate an equation, try holds or solve_for".to_string(), self.node.span,
+            ));
+        }
+        let derived = self.derive(&QuantityMap::new())?;
+        self.node.expand_plus_minus()?.iter()
+            .map(|node| Ok(self.evaluate_node(node, &[&derived, self.var_map()])?.value))
+            .collect()
+    }
+
+    /// Check whether both sides of the equation are equal with variables from \var
+    pub fn holds(&self) -> Result<bool, EvalError> {
+        let (left, right) = self.sides()?;
+        let derived = self.derive(&QuantityMap::new())?;
+        let left = self.evaluate_node(left, &[&derived, self.var_map()])?;
+        let right = self.evaluate_node(right, &[&derived, self.var_map()])?;
+        left.same_dimension(right, "compare").map_err(|e| e.at(self.node.span))?;
+        let (left, right) = (left.value, right.value);
+
+        // allow the error caused by floating point numbers
+        Ok((left - right).abs() <= 1e-9 * left.abs().max(right.abs()).max(1.0))
+    }
+
+    /// Find the value of unknown which makes the equation hold, other variables are taken from \var.
+    /// If unknown is defined by \var as well, the value is used as the initial guess.
+    /// Caution: for an equation with several roots, such as ax^2 + bx + c = 0, only one of them is returned
+    pub fn solve_for(&self, unknown: &str) -> Result<f64, EvalError> {
+        let (left, right) = self.sides()?;
+        let unknown = Exec::var_name(unknown)?;
+
+        // the unknown keeps the unit of its \var
+        let guess = self.var_map().get(&unknown).copied().unwrap_or(Quantity::number(1.0));
+        let mut current = QuantityMap::new();
+        let mut f = |x: f64| -> Result<f64, EvalError> {
+            current.insert(unknown.clone(), Quantity { value: x, ..guess });
+            let derived = self.derive(&current)?;
+            let vars = [&current, &derived, self.var_map()];
+            let difference = self.evaluate_node(left, &vars)?.sub(self.evaluate_node(right, &vars)?);
+            Ok(difference.map_err(|e| e.at(self.node.span))?.value)
+        };
+        let guess = guess.value;
+
+        // report errors such as undefined variables, rather than just saying there is no root
+        f(guess)?;
+        math::util::solve::find_root(|x| f(x).ok(), guess)
+            .ok_or_else(|| EvalError::NoSolution(unknown.clone(), self.node.span))
+    }
+
+    fn sides(&self) -> Result<(&Node, &Node), EvalError> {
+        match (&self.node.node_kind, &self.node.left, &self.node.right) {
+            (NodeKind::Equation, Some(left), Some(right)) => Ok((left, right)),
+            _ => Err(EvalError::Unsupported("This is not an equation".to_string(), self.node.span)),
+        }
+    }
+
+    fn evaluate_node(&self, node: &Node, vars: &[&QuantityMap]) -> Result<Quantity, EvalError> {
+        if node.node_kind != NodeKind::Num {
+            return self.evaluate_op_node(node, vars);
+        }
+
+        match node.value.as_ref() {
+            Some(Token::Function(name, _, _)) if name == "int" => {
+                let (integral, dimension) = self.evaluate_integral(node, vars)?;
+                Ok(Quantity { value: integral.value, dimension })
+            }
+            // an empty sum is 0 and an empty product is 1, which have no unit
+            Some(Token::Function(name, _, _)) if name == "sum" => {
+                Ok(self.fold_indexed(name, node, vars, Quantity::add)?.unwrap_or_default())
+            }
+            Some(Token::Function(name, _, _)) if name == "prod" => {
+                Ok(self.fold_indexed(name, node, vars, Quantity::times)?.unwrap_or(Quantity::number(1.0)))
+            }
+            Some(Token::Function(name, _, _)) => {
+                let fun = self.functions.find(name).map_err(|e| e.at(node.span))?;
+                let op = self.evaluate_args(&node.optional_args, vars)?;
+                let re = self.evaluate_args(&node.required_args, vars)?;
+
+                let boxed = |args: &[Quantity]| -> Vec<Box<dyn Known>> {
+                    args.iter().map(|q| Box::new(q.value) as Box<dyn Known>).collect()
+                };
+                let value = (fun.calc)(boxed(&op), boxed(&re)).map_err(|e| e.at(node.span))?;
+                let dimension = function_dimension(name, &op, &re).map_err(|e| e.at(node.span))?;
+                Ok(Quantity { value, dimension })
+            }
+            Some(Token::Unit(unit)) => Quantity::unit(unit).map_err(|e| e.at(node.span)),
+            Some(Token::Expression(expr)) => {
+                match string_to_known(expr) {
+                    Ok(f) => Ok(Quantity::number(f.get_value())),
+                    // the first map which defines the variable wins, so bindings override \var
+                    Err(_) => match vars.iter().find_map(|map| map.get(expr)) {
+                        Some(q) => Ok(*q),
+                        None => match BUILD_IN_CONSTANT.get(expr.as_str()) {
+                            Some(f) => Ok(Quantity::number(*f)),
+                            None => Err(EvalError::UndefinedVariable(expr.clone(), node.span)),
+                        },
+                    }
                 }
-                _ => Err(format!("Can not evaluate {node:?}")),
             }
-        } else {
-            self.evaluate_op_node(node)
+            value => Err(EvalError::Unsupported(format!("Can not evaluate {value:?}"), node.span)),
+        }
+    }
+
+    /// Arguments of a function are ASTs as well, so they are evaluated with the same variables
+    fn evaluate_args(&self, args: &[Node], vars: &[&QuantityMap]) -> Result<Vec<Quantity>, EvalError> {
+        args.iter()
+            .map(|arg| self.evaluate_node(arg, vars))
+            .collect()
+    }
+
+    /// Combine the values of the body of \sum or \prod with f, such as i^2 for every i from 1 to n in \sum_{i=1}^{n} i^2.
+    /// The index shadows a variable with the same name, and None is returned if there is no term
+    fn fold_indexed<F>(&self, name: &str, node: &Node, vars: &[&QuantityMap], f: F) -> Result<Option<Quantity>, EvalError>
+        where
+            F: Fn(Quantity, Quantity) -> Result<Quantity, EvalError>,
+    {
+        let (Some(sub), Some(sup), Some(body)) = (node.optional_args.first(), node.optional_args.get(1), node.required_args.first()) else {
+            return Err(EvalError::Unsupported(format!("\\{name} needs its limits and body"), node.span));
+        };
+        let (index, lower) = match (&sub.node_kind, sub.left.as_deref(), sub.right.as_deref()) {
+            (NodeKind::Equation, Some(Node { value: Some(Token::Expression(index)), node_kind: NodeKind::Num, .. }), Some(lower))
+            if string_to_known(index).is_err() => (index, lower),
+            _ => return Err(EvalError::Unsupported(
+                format!("The subscript of \\{name} should be like i=1, but gets {}", sub.to_latex()), sub.span,
+            )),
+        };
+
+        let limit = |node: &Node| -> Result<i64, EvalError> {
+            let limit = self.evaluate_node(node, vars)?;
+            if !limit.dimension.is_dimensionless() {
+                return Err(EvalError::DimensionError(format!("The limits of \\{name} can not have a unit"), node.span));
+            }
+            if !limit.value.is_finite() || limit.value.fract() != 0.0 || limit.value.abs() > MAX_EXACT_INTEGER {
+                return Err(EvalError::DomainError(
+                    format!("The limits of \\{name} should be integers, but gets {}", limit.value), node.span,
+                ));
+            }
+            Ok(limit.value as i64)
         };
+        let (lower, upper) = (limit(lower)?, limit(sup)?);
+        if upper - lower >= MAX_TERMS {
+            return Err(EvalError::DomainError(
+                format!("\\{name} from {lower} to {upper} has more than {MAX_TERMS} terms"), node.span,
+            ));
+        }
+
+        // one scope for every term, only the value of the index changes
+        let mut scope = QuantityMap::from([(index.clone(), Quantity::number(lower as f64))]);
+        let mut result = None;
+        for i in lower..=upper {
+            *scope.get_mut(index).unwrap() = Quantity::number(i as f64);
+            let vars: Vec<&QuantityMap> = [&scope].into_iter().chain(vars.iter().copied()).collect();
+            let value = self.evaluate_node(body, &vars)?;
+            result = Some(match result {
+                Some(result) => f(result, value).map_err(|e| e.at(node.span))?,
+                None => value,
+            });
+        }
+
+        Ok(result)
     }
 
-    fn evaluate_op_node(&self, node: &Node) -> Result<f64, String> {
-        let (left, right) = match (
-            self.evaluate_node(&node.left.as_ref().unwrap()),
-            self.evaluate_node(&node.right.as_ref().unwrap()),
-        ) {
-            (Ok(l), Ok(r)) => (l, r),
-            (l, r) => return Err(format!("Node {l:?} or {r:?} can not be evaluated!")),
+    /// \int_a^b f(x) \di{x}, where x takes the unit of the limits, and so does the width of the interval
+    fn evaluate_integral(&self, node: &Node, vars: &[&QuantityMap]) -> Result<(Integral, Dimension), EvalError> {
+        let (Some(lower), Some(upper), Some(body)) = (node.optional_args.first(), node.optional_args.get(1), node.required_args.first()) else {
+            return Err(EvalError::Unsupported("\\int needs its limits and integrand".to_string(), node.span));
         };
+        let (integrand, x) = split_differential(body)?;
+        let (mut lower, mut upper) = (self.evaluate_node(lower, vars)?, self.evaluate_node(upper, vars)?);
+        // 0 is 0 in any unit, such as the lower limit of \int_0^T v \di{t}
+        if lower.value == 0.0 && lower.dimension.is_dimensionless() {
+            lower.dimension = upper.dimension;
+        } else if upper.value == 0.0 && upper.dimension.is_dimensionless() {
+            upper.dimension = lower.dimension;
+        }
+        lower.same_dimension(upper, "integrate between").map_err(|e| e.at(node.span))?;
+        if !lower.value.is_finite() || !upper.value.is_finite() {
+            return Err(EvalError::DomainError(
+                format!("Can not integrate from {} to {}, the limits should be finite", lower.value, upper.value), node.span,
+            ));
+        }
+
+        let mut dimension = Dimension::default();
+        let integral = integrate(|t| {
+            let scope = QuantityMap::from([(x.clone(), Quantity { value: t, dimension: lower.dimension })]);
+            let vars: Vec<&QuantityMap> = [&scope].into_iter().chain(vars.iter().copied()).collect();
+            let y = self.evaluate_node(&integrand, &vars)?;
+            dimension = y.dimension;
+            Ok(y.value)
+        }, lower.value, upper.value, self.settings.integral_tolerance).map_err(|e| match e.span() {
+            // the integrand has already pointed out where it goes wrong
+            span if span == Span::default() => e.at(node.span),
+            _ => e,
+        })?;
+
+        Ok((integral, dimension.times(lower.dimension).map_err(|e| e.at(node.span))?))
+    }
 
+    fn evaluate_op_node(&self, node: &Node, vars: &[&QuantityMap]) -> Result<Quantity, EvalError> {
         let Some(ref op) = node.op else {
-            return Err(format!("Can not get op from {:?}", node));
+            return Err(EvalError::Unsupported(format!("Can not get op from {:?}", node), node.span));
         };
+        let plus_minus = || EvalError::Unsupported("\\pm gives two results, try calculate_all".to_string(), node.span);
+
+        // the error of operands has already pointed out where it is, so just pass it on
+        let right = self.evaluate_node(node.right.as_ref().unwrap(), vars)?;
+        if node.node_kind == NodeKind::Unary {
+            return match op {
+                Token::Sub => Ok(right.neg()),
+                Token::Add => Ok(right),
+                Token::PlusMinus => Err(plus_minus()),
+                o => Err(EvalError::Unsupported(format!("Token {o:?} can not be a unary operator!"), node.span)),
+            };
+        }
+        let left = self.evaluate_node(node.left.as_ref().unwrap(), vars)?;
+
         let result = match op {
-            Token::Add => left + right,
-            Token::Sub => left - right,
-            Token::Times => left * right,
-            Token::Div => left / right,
+            Token::Add => left.add(right).map_err(|e| e.at(node.span))?,
+            Token::Sub => left.sub(right).map_err(|e| e.at(node.span))?,
+            Token::Times => left.times(right).map_err(|e| e.at(node.span))?,
+            Token::Div if right.value == 0.0 => {
+                return Err(EvalError::DomainError(format!("Can not divide {} by zero", left.value), node.span));
+            }
+            Token::Div => left.div(right).map_err(|e| e.at(node.span))?,
+            Token::PlusMinus => return Err(plus_minus()),
             Token::Superscript(_) => {
-                if config::CONFIG.high_accuracy {
-                    math::util::pow::high_accuracy_pow(left, right)
-                } else {
-                    pow(left, right as usize)
+                if !right.dimension.is_dimensionless() {
+                    return Err(EvalError::DimensionError(
+                        format!("The exponent of {} can not have a unit", node.to_latex()), node.span,
+                    ));
                 }
+                left.pow(right.value).map_err(|e| e.at(node.span))?
             }
-            o => return Err(format!("Token {o:?} can not be a operator!")),
+            o => return Err(EvalError::Unsupported(format!("Token {o:?} can not be a operator!"), node.span)),
         };
 
+        // such as a fractional power of a negative number
+        if result.value.is_nan() && !left.value.is_nan() && !right.value.is_nan() {
+            return Err(EvalError::DomainError(format!("The result of {} is not a number", node.to_latex()), node.span));
+        }
+
         Ok(result)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::exec::Exec;
+    use std::f64::consts;
+    use crate::config::{AngleUnit, Settings};
+    use crate::error::EvalError;
+    use crate::exec::{Bindings, Exec};
+    use crate::function::Function;
+    use latex_analyzer::error::ParseError;
     use latex_analyzer::lex::Lex;
+    use latex_analyzer::span::Span;
     use math::util::approx::custom_approx;
 
     #[test]
     fn exec_test1() {
         let lex = Lex::new(r"\frac{1}{2} + \sqrt[3]{4} - \frac{1}{3}".to_string());
-        let exec = Exec::from_lex(lex);
+        let exec = Exec::from_lex(lex).unwrap();
 
         assert_eq!(custom_approx(exec.calculate().unwrap(), 3).unwrap(), 1.754);
     }
@@ -120,17 +593,15 @@ mod tests {
     #[test]
     fn exec_test2() {
         let lex = Lex::new("2^2".to_string());
-        let exec = Exec::from_lex(lex);
+        let exec = Exec::from_lex(lex).unwrap();
 
         assert_eq!(exec.calculate().unwrap(), 4.0);
     }
 
-    /// di has not been implemented yet!
-    #[ignore]
     #[test]
     fn exec_test3() {
         let lex = Lex::new(r"\int_1^2x\di{x}".to_string());
-        let exec = Exec::from_lex(lex);
+        let exec = Exec::from_lex(lex).unwrap();
 
         assert_eq!(exec.calculate().unwrap(), 1.5);
     }
@@ -138,8 +609,465 @@ mod tests {
     #[test]
     fn exec_test4() {
         let lex = Lex::new(r"a+1\var{a=1}".to_string());
-        let exec = Exec::from_lex(lex);
+        let exec = Exec::from_lex(lex).unwrap();
 
         assert_eq!(exec.calculate().unwrap(), 2.0);
     }
+
+    #[test]
+    fn implicit_times_test() {
+        let calc = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap().calculate().unwrap();
+
+        assert_eq!(calc(r"2x\var{x=3}"), 6.0);
+        assert_eq!(calc(r"(a+b)(a-b)\var{a=3}\var{b=1}"), 8.0);
+        assert_eq!(calc(r"a^2{}b\var{a=2}\var{b=3}"), 12.0);
+        assert_eq!(calc(r"\frac{1}{2}x\var{x=4}"), 2.0);
+    }
+
+    #[test]
+    fn unary_test() {
+        let calc = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap().calculate().unwrap();
+
+        assert_eq!(calc(r"-x + 3\var{x=1}"), 2.0);
+        assert_eq!(calc(r"2 * (-a)\var{a=2}"), -4.0);
+        assert_eq!(calc(r"-x^2\var{x=3}"), -9.0);
+        assert_eq!(calc(r"a - -b + +a\var{a=1}\var{b=2}"), 4.0);
+    }
+
+    #[test]
+    fn nested_function_test() {
+        let calc = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap().calculate().unwrap();
+
+        assert_eq!(calc(r"\frac{a+1}{b}\var{a=3}\var{b=2}"), 2.0);
+        assert_eq!(calc(r"\sqrt{\frac{1}{4}}"), 0.5);
+        assert_eq!(calc(r"\sqrt[\frac{6}{2}]{\frac{x}{\sqrt{4}}}\var{x=16}"), 2.0);
+    }
+
+    #[test]
+    fn symbol_test() {
+        let calc = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap().calculate().unwrap();
+
+        assert_eq!(calc(r"\mu{}c\var{\mu=1.8}\var{c=2}"), 3.6);
+        assert_eq!(calc(r"2\pi r\var{r=1}"), consts::TAU);
+        assert_eq!(calc("e"), consts::E);
+        assert_eq!(calc(r"\frac{1}{\infty}"), 0.0);
+        // a variable named e hides the constant
+        assert_eq!(calc(r"e\var{e=2}"), 2.0);
+    }
+
+    #[test]
+    fn subscript_test() {
+        let calc = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap().calculate().unwrap();
+
+        assert_eq!(calc(r"\frac{T}{T_0}\var{T=600}\var{T_0=300}"), 2.0);
+        assert_eq!(calc(r"\frac{\mu{}c_p}{k}\var{\mu=2}\var{c_{p}=3}\var{k=4}"), 1.5);
+        assert_eq!(calc(r"k_{max}^2 - k_{ max }\var{k_{max}=3}"), 6.0);
+    }
+
+    #[test]
+    fn superscript_test() {
+        let calculate = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap().calculate().unwrap();
+
+        assert_eq!(calculate(r"2^{x+1}\var{x=2}"), 8.0);
+        assert_eq!(calculate(r"2^{3^2}"), 512.0);
+        assert_eq!(calculate(r"2^3^2"), 512.0);
+        assert_eq!(calculate(r"{2^3}^2"), 64.0);
+        assert_eq!(calculate(r"2^{-1} + 4^{0.5}"), 2.5);
+        assert_eq!(calculate(r"-2^2"), -4.0);
+        assert_eq!(custom_approx(calculate(r"e^{-\frac{t}{\tau}}\var{t=2}\var{\tau=2}"), 6).unwrap(),
+                   custom_approx(1.0 / consts::E, 6).unwrap());
+        assert_eq!(custom_approx(calculate(r"x^\pi\var{x=1}"), 6).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn operator_symbol_test() {
+        let exec = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap();
+
+        assert_eq!(exec(r"2 \cdot 3 + 4 \times 5").calculate().unwrap(), 26.0);
+        assert_eq!(exec(r"1 + 6 \div 3 \cdot 2").calculate().unwrap(), 5.0);
+        assert_eq!(exec(r"\frac{-b \pm \sqrt{b^2 - 4ac}}{2a}\var{a=1}\var{b=-3}\var{c=2}").calculate_all().unwrap(),
+                   vec![2.0, 1.0]);
+        assert_eq!(exec(r"1 \pm 2 \pm 4").calculate_all().unwrap(), vec![7.0, 3.0, -1.0, -5.0]);
+        assert_eq!(exec("1 + 2").calculate_all().unwrap(), vec![3.0]);
+        assert!(exec(r"1 \pm 2").calculate().is_err());
+    }
+
+    #[test]
+    fn equation_test() {
+        let exec = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap();
+
+        assert!(exec(r"ax^2 + bx + c = 0\var{a=1}\var{b=-3}\var{c=2}\var{x=2}").holds().unwrap());
+        assert!(!exec(r"ax^2 + bx + c = 0\var{a=1}\var{b=-3}\var{c=2}\var{x=3}").holds().unwrap());
+        assert!(exec("1 + 1 = 2").calculate().is_err());
+        assert!(exec("1 + 1").holds().is_err());
+
+        let root = exec(r"ax^2 + bx + c = 0\var{a=1}\var{b=-3}\var{c=2}\var{x=5}").solve_for("x").unwrap();
+        assert_eq!(custom_approx(root, 6).unwrap(), 2.0);
+        let root = exec(r"\frac{k}{k_0} = 2\var{k=3}").solve_for("k_{0}").unwrap();
+        assert_eq!(custom_approx(root, 6).unwrap(), 1.5);
+        assert!(exec(r"x^2 + 1 = 0").solve_for("x").is_err());
+        assert!(exec(r"x + y = 0").solve_for("x").is_err());
+    }
+
+    #[test]
+    fn derivative_test() {
+        let exec = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap();
+        let derivative = |s: &str, x: &str| exec(s).derivative(x).unwrap().calculate().unwrap();
+
+        assert_eq!(derivative(r"x^3 + 2x\var{x=2}", "x"), 14.0);
+        assert_eq!(derivative(r"\frac{1}{x}\var{x=2}", "x"), -0.25);
+        assert_eq!(derivative(r"\sqrt{x}\var{x=4}", "x"), 0.25);
+        assert_eq!(custom_approx(derivative(r"e^{2x}\var{x=0}", "x"), 6).unwrap(), 2.0);
+        assert_eq!(custom_approx(derivative(r"x^x\var{x=1}", "x"), 6).unwrap(), 1.0);
+        assert_eq!(custom_approx(derivative(r"\ln{x^2}\var{x=2}", "x"), 6).unwrap(), 1.0);
+        assert_eq!(derivative(r"\sin{x}\var{x=0}", "x"), 1.0);
+        assert_eq!(custom_approx(derivative(r"\tan{2x}\var{x=0}", "x"), 6).unwrap(), 2.0);
+        assert_eq!(custom_approx(derivative(r"\arctan{x}\var{x=1}", "x"), 6).unwrap(), 0.5);
+        assert_eq!(custom_approx(derivative(r"\cosh{x} + \exp{x}\var{x=0}", "x"), 6).unwrap(), 1.0);
+        assert_eq!(custom_approx(derivative(r"\log_2{x}\var{x=2}", "x"), 6).unwrap(), custom_approx(0.5 / consts::LN_2, 6).unwrap());
+        assert_eq!(custom_approx(derivative(r"\log_x{8}\var{x=2}", "x"), 6).unwrap(), custom_approx(-1.5 / consts::LN_2, 6).unwrap());
+        assert_eq!(derivative(r"k_0 T^2\var{k_0=3}\var{T=1}", "T"), 6.0);
+        assert_eq!(derivative(r"k_0 T^2\var{k_0=3}\var{T=1}", "k_{0}"), 1.0);
+
+        assert_eq!(exec(r"\frac{d}{dx} x^2\var{x=3}").calculate().unwrap(), 6.0);
+        assert_eq!(exec(r"\frac{\di{}}{\di{t}}(t^2 + 3t)\var{t=1}").calculate().unwrap(), 5.0);
+        assert_eq!(exec(r"2\frac{\pa{}}{\pa{x}} xy\var{x=3}\var{y=4}").calculate().unwrap(), 8.0);
+        // variables defined with x are differentiated through their definitions
+        assert_eq!(exec(r"\frac{d}{dx} A\var{A=x^2, x=3}").calculate().unwrap(), 6.0);
+        assert_eq!(exec(r"\frac{d}{dx} 2B\var{A=x^2, B=A + k, k=5}\var{x=1}").calculate().unwrap(), 4.0);
+        assert_eq!(exec(r"B\var{A=x^3, B=\frac{d}{dx} A, x=2}").calculate().unwrap(), 12.0);
+        assert_eq!(derivative(r"A k\var{A=x^2, k=3, x=1}", "x"), 6.0);
+        assert_eq!(derivative(r"A\var{A=x^2, x=1}", "k"), 0.0);
+        assert!(Exec::from_lex(Lex::new(r"\frac{d}{dx}".to_string())).is_err());
+        assert!(exec("x = 1").derivative("x").is_err());
+    }
+
+    #[test]
+    fn simplify_test() {
+        let exec = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap();
+
+        assert_eq!(exec("x^2 + 3x").derivative("x").unwrap().simplify().to_latex(), "2x + 3");
+        assert_eq!(exec(r"\frac{2}{4}x + x\var{x=2}").simplify().calculate().unwrap(), 3.0);
+        assert_eq!(exec(r"a = 1 + 1").simplify().to_latex(), "a = 2");
+        assert_eq!(exec(r"\int_0^1 x \mathrm{d}x").simplify().calculate().unwrap(), 0.5);
+        assert_eq!(custom_approx(exec(r"\int_0^3 x \cdot x \mathrm{d}x").simplify().calculate().unwrap(), 8).unwrap(), 9.0);
+        assert!(matches!(exec(r"\frac{x}{x}\var{x=0}").simplify().calculate(), Err(EvalError::DomainError(..))));
+    }
+
+    #[test]
+    fn parse_error_test() {
+        let source = r"\frac{1}{2} + ?";
+        let lex = Lex::new(source.to_string());
+
+        assert_eq!(
+            Exec::from_lex(lex).err().unwrap().render(source),
+            "I can`t read char: ?\n1 | \\frac{1}{2} + ?\n  |               ^",
+        );
+    }
+
+    #[test]
+    fn parse_var_error_test() {
+        let source = r"a\var{a}";
+        let lex = Lex::new(source.to_string());
+
+        assert_eq!(
+            Exec::from_lex(lex).err().unwrap().render(source),
+            "Can not read variable a\n1 | a\\var{a}\n  |       ^",
+        );
+    }
+
+    #[test]
+    fn error_span_test() {
+        let lex = Lex::new(r"a + \frac{1}{2} * x\var{a=1}".to_string());
+        let exec = Exec::from_lex(lex).unwrap();
+
+        assert_eq!(
+            exec.calculate().unwrap_err().render(exec.source()),
+            "Can not get variable x\n1 | a + \\frac{1}{2} * x\\var{a=1}\n  |                   ^",
+        );
+    }
+
+    #[test]
+    fn error_kind_test() {
+        let err = |s: &str| {
+            Exec::from_lex(Lex::new(s.to_string())).and_then(|exec| exec.calculate()).unwrap_err()
+        };
+
+        assert_eq!(err("1 + x"), EvalError::UndefinedVariable("x".to_string(), Span::new(4, 5)));
+        assert_eq!(err(r"\foo{1}"), EvalError::UnknownFunction("foo".to_string(), Span::new(0, 7)));
+        assert_eq!(err(r"\frac{1}{0}"), EvalError::DomainError("Can not divide 1 by zero".to_string(), Span::new(0, 11)));
+        assert!(matches!(err("1 / (1 - 1)"), EvalError::DomainError(_, _)));
+        assert!(matches!(err(r"\sqrt{-4}"), EvalError::DomainError(_, _)));
+        assert_eq!(
+            err(r"\sqrt[0]{4}"),
+            EvalError::DomainError("The index of a root should be a non-zero integer, but gets 0".to_string(), Span::new(0, 11)),
+        );
+        assert!(matches!(err(r"\sqrt[2.5]{8}"), EvalError::DomainError(_, _)));
+        assert!(matches!(err(r"\sqrt[n]{8}\var{n=10^{10}}"), EvalError::DomainError(_, _)));
+        assert!(matches!(err(r"{-8}^{0.5}"), EvalError::DomainError(_, _)));
+        assert!(matches!(err(r"\frac{1}"), EvalError::WrongArity(_, 2, 1, _)));
+        assert!(matches!(err(r"\frac{1}{2}{3}"), EvalError::WrongArity(_, 2, 3, _)));
+        assert!(matches!(err(r"\sqrt{4}{9}"), EvalError::WrongArity(_, 1, 2, _)));
+        assert!(matches!(err(r"\sin{1}{2}"), EvalError::WrongArity(_, 1, 2, _)));
+        assert!(matches!(err("1 +"), EvalError::Parse(ParseError::MissingOperand(_))));
+        assert_eq!(err(r"a\var{a=b}"), EvalError::UndefinedVariable("b".to_string(), Span::new(8, 9)));
+        assert!(matches!(err(r"a\var{a=b, b=a}"), EvalError::CircularVariable(_, _)));
+        assert!(matches!(err(r"x^2 = 1"), EvalError::Unsupported(_, _)));
+
+        let calc = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap().calculate().unwrap();
+        assert_eq!(calc(r"\sqrt[3]{-8}"), -2.0);
+        assert_eq!(calc(r"\sqrt[-2]{4}"), 0.5);
+    }
+
+    #[test]
+    fn calculate_with_test() {
+        let exec = Exec::from_lex(Lex::new(r"a x + T_0\var{a=2}\var{T_{0}=1}".to_string())).unwrap();
+
+        let bindings = Bindings::new().set("x", 3.0).unwrap();
+        assert_eq!(exec.calculate_with(&bindings).unwrap(), 7.0);
+        // bindings override \var, and names are read like LaTeX
+        let bindings = bindings.set("a", 10.0).unwrap().set("T_{0}", 0.5).unwrap();
+        assert_eq!(exec.calculate_with(&bindings).unwrap(), 30.5);
+        assert_eq!(bindings.get("T_0"), Some(0.5));
+
+        let results: Vec<f64> = (0..3)
+            .map(|x| exec.calculate_with(&Bindings::new().set("x", x as f64).unwrap()).unwrap())
+            .collect();
+        assert_eq!(results, vec![1.0, 3.0, 5.0]);
+
+        assert!(matches!(exec.calculate_with(&Bindings::new()), Err(EvalError::UndefinedVariable(..))));
+        assert!(matches!(Bindings::new().set("a+b", 1.0), Err(EvalError::InvalidVariable(..))));
+    }
+
+    #[test]
+    fn definition_test() {
+        let exec = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap();
+
+        assert_eq!(exec(r"A\var{r=2}\var{A=\pi r^2}").calculate().unwrap(), 4.0 * consts::PI);
+        assert_eq!(exec(r"c\var{c=a+b, b=2a, a=1}").calculate().unwrap(), 3.0);
+        assert_eq!(exec(r"y\var{e=2, y=e^2}").calculate().unwrap(), 4.0);
+
+        // definitions follow bound variables, unless they are bound themselves
+        let area = exec(r"2A\var{r=2}\var{A=\pi r^2}");
+        let bindings = Bindings::new().set("r", 1.0).unwrap();
+        assert_eq!(area.calculate_with(&bindings).unwrap(), 2.0 * consts::PI);
+        assert_eq!(area.compile().unwrap().calculate_with(&bindings).unwrap(), 2.0 * consts::PI);
+        let bindings = bindings.set("A", 1.0).unwrap();
+        assert_eq!(area.calculate_with(&bindings).unwrap(), 2.0);
+        assert_eq!(area.compile().unwrap().calculate_with(&bindings).unwrap(), 2.0);
+
+        let equation = exec(r"A = 4\pi\var{A=\pi r^2}\var{r=1}");
+        assert_eq!(custom_approx(equation.solve_for("r").unwrap(), 6).unwrap(), 2.0);
+
+        // a definition may use a variable which is only bound later
+        let area = exec(r"A\var{A=\pi r^2}");
+        assert_eq!(area.calculate(), Err(EvalError::UndefinedVariable("r".to_string(), Span::new(12, 13))));
+        let bindings = Bindings::new().set("r", 2.0).unwrap();
+        assert_eq!(area.calculate_with(&bindings).unwrap(), 4.0 * consts::PI);
+        assert_eq!(area.compile().unwrap().calculate_with(&bindings).unwrap(), 4.0 * consts::PI);
+        assert!(matches!(area.compile().unwrap().calculate(), Err(EvalError::UndefinedVariable(..))));
+        assert_eq!(exec(r"A = 4\pi\var{A=\pi r^2}").solve_for("r").map(|r| custom_approx(r, 6).unwrap()), Ok(2.0));
+        assert!(matches!(Exec::from_lex(Lex::new(r"a\var{a=b, b=a}".to_string())), Err(EvalError::CircularVariable(..))));
+    }
+
+    #[test]
+    fn unit_test() {
+        let exec = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap();
+
+        let distance = exec(r"v t\var{v=3\,\mathrm{m/s}}\var{t=2\,\mathrm{min}}").calculate_quantity().unwrap();
+        assert_eq!(distance.to_string(), r"360\,\mathrm{m}");
+        assert_eq!(distance.to("km").unwrap(), 0.36);
+
+        let speed = exec(r"\frac{1\,\mathrm{km}}{1\,\mathrm{h}} + 1\,\mathrm{m/s}").calculate_quantity().unwrap();
+        assert_eq!(custom_approx(speed.to("km/h").unwrap(), 6).unwrap(), 4.6);
+        let side = exec(r"\sqrt{A}\var{A=4\,\mathrm{m^2}}").calculate_quantity().unwrap();
+        assert_eq!(side.to_string(), r"2\,\mathrm{m}");
+        // a bound value takes the unit of the \var it overrides
+        let area = exec(r"l^2\var{l=1\,\mathrm{m}}");
+        assert_eq!(area.calculate_with(&Bindings::new().set("l", 3.0).unwrap()).unwrap(), 9.0);
+        assert!(exec(r"F = m a\var{F=2\,\mathrm{N}}\var{m=1\,\mathrm{kg}}\var{a=2\,\mathrm{m/s^2}}").holds().unwrap());
+
+        let err = |s: &str| exec(s).calculate().unwrap_err();
+        assert_eq!(
+            err(r"1\,\mathrm{m} + 1\,\mathrm{s}"),
+            EvalError::DimensionError("Can not add m and s".to_string(), Span::new(0, 29)),
+        );
+        assert!(matches!(err(r"2^{1\,\mathrm{s}}"), EvalError::DimensionError(..)));
+        assert!(matches!(err(r"\ln{1\,\mathrm{m}}"), EvalError::DimensionError(..)));
+        // not a unit, so it is a variable named \mathrm{furlong}
+        assert!(matches!(err(r"1\,\mathrm{furlong}"), EvalError::UndefinedVariable(..)));
+        assert_eq!(exec(r"2\mathrm{Re}\var{\mathrm{Re}=3}").calculate().unwrap(), 6.0);
+        assert!(matches!(exec(r"x = 1\var{x=1\,\mathrm{m}}").holds(), Err(EvalError::DimensionError(..))));
+        // exponents of units are bounded, an overflow is an error rather than a panic
+        assert!(matches!(err(r"{1\,\mathrm{m}}^{100}{1\,\mathrm{m}}^{100}"), EvalError::DimensionError(..)));
+        assert!(matches!(err(r"\prod_{i=1}^{200} l\var{l=1\,\mathrm{m}}"), EvalError::DimensionError(..)));
+        assert!(matches!(err(r"{1\,\mathrm{m}}^{300}"), EvalError::DimensionError(..)));
+    }
+
+    #[test]
+    fn sum_test() {
+        let exec = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap();
+
+        assert_eq!(exec(r"\sum_{i=1}^{n} i^2\var{n=10}").calculate().unwrap(), 385.0);
+        assert_eq!(exec(r"\sum_{i=1}^{n} i^2\var{n=10}").calculate_with(&Bindings::new().set("n", 3.0).unwrap()).unwrap(), 14.0);
+        assert_eq!(exec(r"\sum_{k=0}^{3} 2^k + 1").calculate().unwrap(), 16.0);
+        // the upper limit of the inner sum uses the index of the outer one
+        assert_eq!(exec(r"\sum_{i=1}^{3} \sum_{j=1}^{i} ij").calculate().unwrap(), 25.0);
+        assert_eq!(exec(r"\sum_{i=1}^{2} \sum_{i=1}^{3} i").calculate().unwrap(), 12.0);
+        assert_eq!(exec(r"\sum_{i=1}^{0} i").calculate().unwrap(), 0.0);
+        let length = exec(r"\sum_{i=1}^{3} i l\var{l=1\,\mathrm{cm}}").calculate_quantity().unwrap();
+        assert_eq!(custom_approx(length.to("m").unwrap(), 6).unwrap(), 0.06);
+
+        let err = |s: &str| exec(s).calculate().unwrap_err();
+        assert!(matches!(err(r"\sum_{i=1}^{2} l^i\var{l=1\,\mathrm{m}}"), EvalError::DimensionError(..)));
+        assert!(matches!(err(r"\sum_{i=1}^{\infty} i"), EvalError::DomainError(..)));
+        assert!(matches!(err(r"\sum_{i=1}^{2\,\mathrm{s}} i"), EvalError::DimensionError(..)));
+        // too many terms, and limits which do not fit in an integer
+        assert_eq!(
+            err(r"\sum_{i=1}^{10^{8}} i"),
+            EvalError::DomainError(r"\sum from 1 to 100000000 has more than 10000000 terms".to_string(), Span::new(0, 21)),
+        );
+        assert!(matches!(err(r"\sum_{i=10^{30}}^{10^{30}} i"), EvalError::DomainError(..)));
+        assert!(matches!(err(r"\prod_{i=-10^{30}}^{1} i"), EvalError::DomainError(..)));
+    }
+
+    #[test]
+    fn product_test() {
+        let exec = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap();
+
+        assert_eq!(exec(r"\prod_{i=1}^{5} i").calculate().unwrap(), 120.0);
+        assert_eq!(exec(r"\prod_{k=1}^{n} (1 + \frac{1}{k})\var{n=4}").calculate().unwrap(), 5.0);
+        assert_eq!(exec(r"\prod_{i=1}^{2} \prod_{j=1}^{3} (i + j)").calculate().unwrap(), 1440.0);
+        assert_eq!(exec(r"\prod_{i=3}^{2} i").calculate().unwrap(), 1.0);
+        // the index only lives in the body
+        assert_eq!(exec(r"i\prod_{i=1}^{3} i\var{i=2}").calculate().unwrap(), 12.0);
+        let area = exec(r"\prod_{i=1}^{2} l\var{l=3\,\mathrm{m}}").calculate_quantity().unwrap();
+        assert_eq!(area.to("m^2").unwrap(), 9.0);
+
+        let err = |s: &str| exec(s).calculate().unwrap_err();
+        assert_eq!(
+            err(r"\prod_{i=1}^{2.5} i"),
+            EvalError::DomainError("The limits of \\prod should be integers, but gets 2.5".to_string(), Span::new(13, 16)),
+        );
+        assert!(matches!(err(r"\prod_{1}^{3} i"), EvalError::Unsupported(..)));
+        assert!(matches!(err(r"\prod_{i=1}^{n} i"), EvalError::UndefinedVariable(..)));
+        assert!(matches!(exec(r"\prod_{i=1}^{3} i").compile(), Err(EvalError::Unsupported(..))));
+    }
+
+    #[test]
+    fn integral_test() {
+        let exec = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap();
+        let calc = |s: &str| custom_approx(exec(s).calculate().unwrap(), 8).unwrap();
+
+        assert_eq!(calc(r"\int_0^1 x^2 \di{x}"), 0.33333333);
+        assert_eq!(calc(r"\int_0^{\pi} \sin x \mathrm{d}x + 1"), 3.0);
+        assert_eq!(calc(r"\int_1^e \frac{1}{t} \di{t}"), 1.0);
+        assert_eq!(calc(r"\int_0^a k x \di{x}\var{a=2, k=3}"), 6.0);
+        assert_eq!(calc(r"\int_0^1 \int_0^x xy \di{y} \di{x}"), 0.125);
+        assert_eq!(calc(r"\int_0^2 \di{x}"), 2.0);
+
+        let integral = exec(r"\int_0^1 e^{-x^2} \di{x}").calculate_integral().unwrap();
+        assert_eq!(custom_approx(integral.value, 8).unwrap(), 0.74682413);
+        assert!(integral.error <= 1e-10);
+        assert!(matches!(exec(r"1 + x").calculate_integral(), Err(EvalError::Unsupported(..))));
+        // a velocity integrated over time gives a distance
+        let distance = exec(r"\int_0^T v \di{t}\var{v=2\,\mathrm{m/s}, T=3\,\mathrm{s}}").calculate_quantity().unwrap();
+        assert_eq!(distance.to("m").unwrap(), 6.0);
+
+        let err = |s: &str| exec(s).calculate().unwrap_err();
+        assert!(matches!(err(r"\int_0^1 x"), EvalError::Unsupported(..)));
+        assert!(matches!(err(r"\int_0^{\infty} e^{-x} \di{x}"), EvalError::DomainError(..)));
+        assert_eq!(
+            err(r"\int_{-1}^1 \frac{1}{x} \di{x}"),
+            EvalError::DomainError("Can not divide 1 by zero".to_string(), Span::new(12, 23)),
+        );
+        assert!(matches!(err(r"\int_1^{2\,\mathrm{s}} x \di{x}"), EvalError::DimensionError(..)));
+    }
+
+    #[test]
+    fn integral_tolerance_test() {
+        let integral = |tolerance: f64| {
+            let settings = Settings::new().integral_tolerance(tolerance).unwrap();
+            let lex = Lex::new(r"\int_0^1 \sqrt{x} \di{x}".to_string());
+            Exec::from_lex_with(lex, settings).unwrap().calculate_integral()
+        };
+
+        // \sqrt{x} is steep near 0, so a loose tolerance stops early
+        let (loose, tight) = (integral(1e-3).unwrap(), integral(1e-10).unwrap());
+        assert_ne!(loose.value, tight.value);
+        assert!((tight.value - 2.0 / 3.0).abs() < (loose.value - 2.0 / 3.0).abs());
+        assert!(tight.error < loose.error);
+        // and the smallest pieces near 0 are still not accurate enough for this one
+        assert!(matches!(integral(1e-12), Err(EvalError::DomainError(..))));
+        assert_eq!(Settings::new().get_integral_tolerance(), 1e-10);
+        assert!(matches!(Settings::new().integral_tolerance(0.0), Err(EvalError::DomainError(..))));
+        assert!(matches!(Settings::new().integral_tolerance(f64::NAN), Err(EvalError::DomainError(..))));
+    }
+
+    #[test]
+    fn function_scope_test() {
+        let exec = Exec::from_lex(Lex::new(r"\scale{2} + \ln{x}\var{x=4}".to_string())).unwrap();
+        assert!(matches!(exec.calculate(), Err(EvalError::UnknownFunction(..))));
+
+        let factor = 10.0;
+        exec.functions().register(Function::new("scale", move |_, r| Ok(r[0].get_value() * factor))).unwrap();
+        exec.functions().override_build_in(Function::new("ln", |_, r| Ok(r[0].get_value().log2()))).unwrap();
+        assert_eq!(exec.calculate().unwrap(), 22.0);
+        assert_eq!(exec.compile().unwrap().calculate().unwrap(), 22.0);
+        assert_eq!(exec.functions().names(), vec!["ln", "scale"]);
+
+        exec.functions().unregister("ln");
+        assert_eq!(exec.calculate().unwrap(), 20.0 + 4f64.ln());
+        // other expressions do not see the functions of this one
+        assert!(Exec::from_lex(Lex::new(r"\scale{2}".to_string())).unwrap().calculate().is_err());
+    }
+
+    #[test]
+    fn elementary_function_test() {
+        let calc = |s: &str| custom_approx(Exec::from_lex(Lex::new(s.to_string())).unwrap().calculate().unwrap(), 6).unwrap();
+
+        assert_eq!(calc(r"\sin{\frac{\pi}{2}}"), 1.0);
+        assert_eq!(calc(r"\sin^2 x + \cos^2 x\var{x=0.3}"), 1.0);
+        assert_eq!(calc(r"2\sin x \cos x - \sin 2x\var{x=0.7}"), 0.0);
+        assert_eq!(calc(r"\sin^{-1} 1 - \arcsin(1)"), 0.0);
+        assert_eq!(calc(r"\tan\frac{\pi}{4}"), 1.0);
+        assert_eq!(calc(r"\log_2 8 + \log 100 + \ln e"), 6.0);
+        assert_eq!(calc(r"\exp x - e^x\var{x=1.5}"), 0.0);
+        assert_eq!(calc(r"\cosh^2 x - \sinh^2 x\var{x=2}"), 1.0);
+        assert_eq!(calc(r"\artanh(\tanh 0.5)"), 0.5);
+
+        let err = |s: &str| Exec::from_lex(Lex::new(s.to_string())).unwrap().calculate().unwrap_err();
+        assert!(matches!(err(r"\arcsin 2"), EvalError::DomainError(..)));
+        assert!(matches!(err(r"\cot 0"), EvalError::DomainError(..)));
+        assert!(matches!(err(r"\artanh 1"), EvalError::DomainError(..)));
+        assert!(matches!(err(r"\exp{1000}"), EvalError::Overflow(..)));
+        assert!(matches!(err(r"\cosh 1000"), EvalError::Overflow(..)));
+        assert!(matches!(err(r"\log_1 5"), EvalError::DomainError(..)));
+        assert!(matches!(err(r"\log(-1)"), EvalError::DomainError(..)));
+        assert!(matches!(err(r"\arcosh 0"), EvalError::DomainError(..)));
+        assert!(matches!(err(r"\sin{1\,\mathrm{m}}"), EvalError::DimensionError(..)));
+    }
+
+    #[test]
+    fn angle_unit_test() {
+        let degree = |s: &str| {
+            Exec::from_lex_with(Lex::new(s.to_string()), Settings::new().angle_unit(AngleUnit::Degree)).unwrap()
+        };
+        let calc = |s: &str| custom_approx(degree(s).calculate().unwrap(), 6).unwrap();
+
+        assert_eq!(calc(r"\sin 90"), 1.0);
+        assert_eq!(calc(r"\cos 180 + \tan 45"), 0.0);
+        assert_eq!(calc(r"\arcsin 1"), 90.0);
+        assert_eq!(degree(r"\sin 30").compile().unwrap().calculate().unwrap(), degree(r"\sin 30").calculate().unwrap());
+        // derivatives scale by the unit as well
+        assert_eq!(calc(r"\frac{d}{dx} \sin x\var{x=0}"), custom_approx(consts::PI / 180.0, 6).unwrap());
+        assert_eq!(custom_approx(degree(r"\arctan x\var{x=0}").derivative("x").unwrap().calculate().unwrap(), 6).unwrap(),
+                   custom_approx(180.0 / consts::PI, 6).unwrap());
+        // hyperbolic functions do not take angles
+        assert_eq!(calc(r"\sinh 0 + \cosh 0"), 1.0);
+
+        let radian = Exec::from_lex(Lex::new(r"\sin 90".to_string())).unwrap();
+        assert_eq!(radian.settings().get_angle_unit(), AngleUnit::Radian);
+        assert_eq!(custom_approx(radian.calculate().unwrap(), 6).unwrap(), custom_approx(90f64.sin(), 6).unwrap());
+    }
 }
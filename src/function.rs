@@ -1,63 +1,207 @@
+//! [crate::function] finds the function which a name such as \frac stands for.
+//! Besides built-in functions, the caller can register its own ones into a [FunctionRegistry],
+//! either the global one which every [crate::exec::Exec] uses, or the one of a single [crate::exec::Exec]
+//!
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use lazy_static::lazy_static;
+use latex_analyzer::span::Span;
 use crate::buildin_function::*;
+use crate::config::{AngleUnit, CONFIG};
+use crate::error::EvalError;
 use crate::known::Known;
 
-type Container = Vec<Box<dyn Known>>;
-type CalcContainer = fn(Container, Container) -> Option<f64>;
+pub type Container = Vec<Box<dyn Known>>;
+type CalcContainer = dyn Fn(Container, Container) -> Result<f64, EvalError> + Send + Sync;
 
-#[derive(Debug)]
 pub struct Function {
     pub name: String,
-    pub calc: CalcContainer,
+    pub calc: Box<CalcContainer>,
 }
 
 impl Function {
-    pub fn new(name: &str, calc: CalcContainer) -> Self {
+    /// calc takes optional arguments and required arguments, such as [3] and [8] for \sqrt[3]{8}.
+    /// It may capture values, so a function can be made at runtime
+    pub fn new<F>(name: &str, calc: F) -> Self
+        where
+            F: Fn(Container, Container) -> Result<f64, EvalError> + Send + Sync + 'static,
+    {
         Function {
             name: name.to_string(),
-            calc,
+            calc: Box::new(calc),
         }
     }
 }
 
-pub fn register_extern_function(fun: Function) -> Result<(), String> {
-    unsafe {
-        EXTERN_FUNCTION.push(fun);
+impl Debug for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Function").field("name", &self.name).finish_non_exhaustive()
     }
-    Ok(())
 }
 
-/// build-in functions take the priority,
-/// so if there`s an extern function which has a same name as a build-in function,
-/// the extern function will never be gotten
-pub fn get_function<'a>(name: &String) -> Result<&'a Function, String> {
-    if let Some(fun) =
-        BUILD_IN_FUNCTION.iter().find(|f| f.name == *name)
-    {
-        return Ok(fun);
-    } else {
-        unsafe {
-            if let Some(fun) =
-                EXTERN_FUNCTION.iter().find(|f| f.name == *name)
-            {
-                return Ok(fun);
-            }
+lazy_static! {
+    static ref GLOBAL_REGISTRY: FunctionRegistry = FunctionRegistry::new();
+}
+
+/// Functions registered by the caller, which can be shared between threads.
+/// A registered function can not take the name of a built-in one, unless it overrides the built-in one on purpose
+pub struct FunctionRegistry {
+    functions: RwLock<HashMap<String, Arc<Function>>>,
+    // the unit of angles which built-in trigonometric functions use
+    angle_unit: AngleUnit,
+}
+
+impl FunctionRegistry {
+    pub fn new() -> Self {
+        FunctionRegistry::default()
+    }
+
+    pub fn angle_unit(&self) -> AngleUnit {
+        self.angle_unit
+    }
+
+    pub(crate) fn set_angle_unit(&mut self, unit: AngleUnit) {
+        self.angle_unit = unit;
+    }
+
+    /// The registry every [crate::exec::Exec] uses, functions of an [crate::exec::Exec] take the priority
+    pub fn global() -> &'static FunctionRegistry {
+        &GLOBAL_REGISTRY
+    }
+
+    /// A registered function with the same name is replaced.
+    /// Names which the parser reads by itself, such as \sum or \var, can not be registered
+    pub fn register(&self, fun: Function) -> Result<(), EvalError> {
+        if RESERVED_NAME.contains(&fun.name) {
+            return Err(invalid_registration(format!("\\{} is reserved, it can not be a function", fun.name)));
+        }
+        if is_build_in(&fun.name) {
+            return Err(invalid_registration(
+                format!("\\{} is a built-in function, use override_build_in to replace it", fun.name),
+            ));
+        }
+        self.write().insert(fun.name.clone(), Arc::new(fun));
+        Ok(())
+    }
+
+    /// Use fun instead of the built-in function with the same name, such as \log for the natural logarithm
+    pub fn override_build_in(&self, fun: Function) -> Result<(), EvalError> {
+        if !is_build_in(&fun.name) {
+            return Err(invalid_registration(
+                format!("There is no built-in function \\{}, use register to add it", fun.name),
+            ));
+        }
+        self.write().insert(fun.name.clone(), Arc::new(fun));
+        Ok(())
+    }
+
+    /// Remove a registered function, an overridden built-in function is used again
+    pub fn unregister(&self, name: &str) -> Option<Arc<Function>> {
+        self.write().remove(name)
+    }
+
+    /// Names of registered functions in alphabetical order
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.read().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Function>> {
+        self.read().get(name).cloned()
+    }
+
+    /// Functions registered here take the priority, then the global ones, and built-in functions come last
+    pub fn find(&self, name: &str) -> Result<Arc<Function>, EvalError> {
+        let global = FunctionRegistry::global();
+        let registered = self.get(name).or_else(|| if std::ptr::eq(self, global) { None } else { global.get(name) });
+
+        registered
+            .or_else(|| ANGLE_FUNCTION[&self.angle_unit].iter().find(|f| f.name == name).cloned())
+            .or_else(|| BUILD_IN_FUNCTION.iter().find(|f| f.name == name).cloned())
+            .ok_or_else(|| EvalError::UnknownFunction(name.to_string(), Span::default()))
+    }
+
+    // functions are never called while the lock is held, so a poisoned lock still has a usable map
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<String, Arc<Function>>> {
+        self.functions.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, Arc<Function>>> {
+        self.functions.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        FunctionRegistry {
+            functions: RwLock::default(),
+            angle_unit: CONFIG.angle_unit,
+        }
+    }
+}
+
+impl Clone for FunctionRegistry {
+    fn clone(&self) -> Self {
+        FunctionRegistry {
+            functions: RwLock::new(self.read().clone()),
+            angle_unit: self.angle_unit,
         }
     }
+}
+
+impl Debug for FunctionRegistry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FunctionRegistry")
+            .field("functions", &self.names())
+            .field("angle_unit", &self.angle_unit)
+            .finish()
+    }
+}
+
+fn is_build_in(name: &str) -> bool {
+    BUILD_IN_FUNCTION.iter().any(|f| f.name == name)
+}
+
+/// Registering happens before any expression, so there is no span to point at
+fn invalid_registration(message: String) -> EvalError {
+    EvalError::InvalidRegistration(message, Span::default())
+}
 
-    Err(format!("Can`t get the function: {name}"))
+/// Register a function into [FunctionRegistry::global]
+pub fn register_extern_function(fun: Function) -> Result<(), EvalError> {
+    FunctionRegistry::global().register(fun)
+}
+
+/// A function registered into [FunctionRegistry::global] or a built-in one
+pub fn get_function(name: &str) -> Result<Arc<Function>, EvalError> {
+    FunctionRegistry::global().find(name)
+}
+
+/// Make sure a function gets exactly expected arguments, so indexing them will not panic
+/// and extra ones, such as the {3} of \frac{1}{2}{3}, are not silently dropped
+pub fn check_arity(name: &str, args: &Container, expected: usize) -> Result<(), EvalError> {
+    if args.len() != expected {
+        return Err(EvalError::WrongArity(name.to_string(), expected, args.len(), Span::default()));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::function::{Function, get_function, register_extern_function};
+    use std::sync::Arc;
+    use std::thread;
+    use crate::error::EvalError;
+    use crate::function::{Container, Function, FunctionRegistry, get_function, register_extern_function};
 
     #[test]
     fn function_test() {
         let frac = Function {
             name: "frac".to_string(),
-            calc: |_o, r| {
-                Some(r[0].get_value() / r[1].get_value())
-            },
+            calc: Box::new(|_o, r| {
+                Ok(r[0].get_value() / r[1].get_value())
+            }),
         };
 
         let a = (frac.calc)(vec![], vec![Box::new(1.0), Box::new(2.0)]);
@@ -69,19 +213,70 @@ mod tests {
 
     #[test]
     fn get_function_test() {
-        let fun = get_function(&"frac".to_string()).unwrap();
+        let fun = get_function("frac").unwrap();
         assert_eq!(fun.name, "frac");
         assert_eq!((fun.calc)(vec![], vec![Box::new(1.0), Box::new(2.0)]).unwrap(), 0.5);
+        assert!(matches!(get_function("unknown"), Err(EvalError::UnknownFunction(_, _))));
+        assert!(matches!((fun.calc)(vec![], vec![Box::new(1.0)]), Err(EvalError::WrongArity(_, 2, 1, _))));
+        let three: Container = vec![Box::new(1.0), Box::new(2.0), Box::new(3.0)];
+        assert!(matches!((fun.calc)(vec![], three), Err(EvalError::WrongArity(_, 2, 3, _))));
     }
 
     #[test]
     fn register_function_test() {
         let re = Function::new("double", |_o, r| {
-            Some(r[0].get_value() * 2.0)
+            Ok(r[0].get_value() * 2.0)
         });
 
         register_extern_function(re).expect("Register function failed!");
-        let fun = get_function(&"double".to_string()).unwrap();
+        let fun = get_function("double").unwrap();
         assert_eq!((fun.calc)(vec![], vec![Box::new(10.0)]).unwrap(), 20.0);
     }
+
+    #[test]
+    fn registry_test() {
+        let registry = FunctionRegistry::new();
+        let scale = 3.0;
+        registry.register(Function::new("triple", move |_, r| Ok(r[0].get_value() * scale))).unwrap();
+        assert_eq!((registry.find("triple").unwrap().calc)(vec![], vec![Box::new(2.0)]).unwrap(), 6.0);
+
+        // built-in functions are only replaced on purpose
+        let invalid = |result| matches!(result, Err(EvalError::InvalidRegistration(..)));
+        assert!(invalid(registry.register(Function::new("ln", |_, _| Ok(0.0)))));
+        assert!(invalid(registry.override_build_in(Function::new("triple", |_, _| Ok(0.0)))));
+        // names the parser reads by itself are never called as functions
+        for name in ["sum", "int", "prod", "di", "var", "mathrm", "cdot"] {
+            assert!(invalid(registry.register(Function::new(name, |_, _| Ok(0.0)))));
+            assert!(invalid(registry.override_build_in(Function::new(name, |_, _| Ok(0.0)))));
+        }
+        assert!(invalid(register_extern_function(Function::new("pm", |_, _| Ok(0.0)))));
+        registry.override_build_in(Function::new("ln", |_, r| Ok(r[0].get_value()))).unwrap();
+        assert_eq!((registry.find("ln").unwrap().calc)(vec![], vec![Box::new(2.0)]).unwrap(), 2.0);
+        assert_eq!(registry.names(), vec!["ln", "triple"]);
+
+        assert!(registry.unregister("ln").is_some());
+        assert!(registry.unregister("ln").is_none());
+        assert_eq!((registry.find("ln").unwrap().calc)(vec![], vec![Box::new(1.0)]).unwrap(), 0.0);
+        // the global registry does not see functions of others
+        assert!(get_function("triple").is_err());
+    }
+
+    #[test]
+    fn registry_thread_test() {
+        let registry = Arc::new(FunctionRegistry::new());
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let registry = Arc::clone(&registry);
+                thread::spawn(move || {
+                    let name = format!("f{i}");
+                    registry.register(Function::new(&name, move |_, _| Ok(i as f64))).unwrap();
+                    (registry.find(&name).unwrap().calc)(vec![], vec![]).unwrap()
+                })
+            })
+            .collect();
+
+        let results: Vec<f64> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results, (0..8).map(|i| i as f64).collect::<Vec<f64>>());
+        assert_eq!(registry.names().len(), 8);
+    }
 }
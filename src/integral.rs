@@ -0,0 +1,168 @@
+//! [crate::integral] calculates definite integrals, such as \int_0^1 x^2 \di{x}.
+//! The integrand is calculated wherever it is needed, and the interval is split by the adaptive Simpson method
+//! until every piece is accurate enough
+//!
+use latex_analyzer::ast::Node;
+use latex_analyzer::lex::Token;
+use latex_analyzer::span::Span;
+use crate::derivative::{flatten_product, num, product};
+use crate::error::EvalError;
+
+// pieces are split at least this many times, so an integrand which happens to agree with Simpson's rule
+// on a few points, such as \sin x from 0 to 2\pi, is still sampled enough
+const MIN_DEPTH: u32 = 4;
+// a piece is at least 2^-20 of the interval, it is not split further even if it is not accurate enough
+const MAX_DEPTH: u32 = 20;
+
+/// The value of an integral and the estimate of its error
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Integral {
+    pub value: f64,
+    pub error: f64,
+}
+
+/// Integrate f from lower to upper, the estimated error is within tolerance or it is an error,
+/// such as f being singular or too wild near a point. Limits should be finite, and lower may be greater than upper
+pub fn integrate<F>(mut f: F, lower: f64, upper: f64, tolerance: f64) -> Result<Integral, EvalError>
+    where
+        F: FnMut(f64) -> Result<f64, EvalError>,
+{
+    if !lower.is_finite() || !upper.is_finite() {
+        return Err(EvalError::DomainError(
+            format!("Can not integrate from {lower} to {upper}, the limits should be finite"), Span::default(),
+        ));
+    }
+
+    let (fa, fb) = (f(lower)?, f(upper)?);
+    let integral = Piece::new(&mut f, lower, fa, upper, fb)?.refine(&mut f, tolerance, 0)?;
+    // pieces which stop at MAX_DEPTH keep their error, so a sum above tolerance means it does not converge
+    if integral.error > tolerance {
+        return Err(EvalError::DomainError(
+            format!("The integral does not converge within {tolerance}, its error is about {}", integral.error),
+            Span::default(),
+        ));
+    }
+    Ok(integral)
+}
+
+/// [a, b] with the values of f at both ends and the middle, and Simpson's estimate of the integral on it
+struct Piece {
+    a: f64,
+    fa: f64,
+    m: f64,
+    fm: f64,
+    b: f64,
+    fb: f64,
+    area: f64,
+}
+
+impl Piece {
+    fn new<F>(f: &mut F, a: f64, fa: f64, b: f64, fb: f64) -> Result<Piece, EvalError>
+        where
+            F: FnMut(f64) -> Result<f64, EvalError>,
+    {
+        let m = (a + b) / 2.0;
+        let fm = f(m)?;
+        let area = (b - a) / 6.0 * (fa + 4.0 * fm + fb);
+
+        Ok(Piece { a, fa, m, fm, b, fb, area })
+    }
+
+    /// Split the piece until its halves agree with it, the error of the halves is about 1/15 of the difference
+    fn refine<F>(&self, f: &mut F, tolerance: f64, depth: u32) -> Result<Integral, EvalError>
+        where
+            F: FnMut(f64) -> Result<f64, EvalError>,
+    {
+        let left = Piece::new(f, self.a, self.fa, self.m, self.fm)?;
+        let right = Piece::new(f, self.m, self.fm, self.b, self.fb)?;
+        let delta = left.area + right.area - self.area;
+
+        if !delta.is_finite() {
+            return Err(EvalError::DomainError(format!("The integral does not converge near {}", self.m), Span::default()));
+        }
+        if depth >= MAX_DEPTH || (depth >= MIN_DEPTH && delta.abs() <= 15.0 * tolerance) {
+            return Ok(Integral { value: left.area + right.area + delta / 15.0, error: delta.abs() / 15.0 });
+        }
+
+        let left = left.refine(f, tolerance / 2.0, depth + 1)?;
+        let right = right.refine(f, tolerance / 2.0, depth + 1)?;
+        Ok(Integral { value: left.value + right.value, error: left.error + right.error })
+    }
+}
+
+/// The integrand and the name of the variable from the body of \int,
+/// whose last factor is the differential, such as x^2 \di{x} or x^2 \mathrm{d}x
+pub(crate) fn split_differential(body: &Node) -> Result<(Node, String), EvalError> {
+    let mut factors = Vec::new();
+    flatten_product(body, &mut factors);
+
+    let variable = |node: &Node| match &node.value {
+        Some(Token::Expression(x)) if !x.starts_with(|c: char| c.is_ascii_digit()) => Some(x.clone()),
+        _ => None,
+    };
+    let (x, count) = match factors.as_slice() {
+        [.., last] if matches!(&last.value, Some(Token::Function(f, _, _)) if f == "di") => {
+            (last.required_args.first().and_then(variable), 1)
+        }
+        [.., d, last] if matches!(&d.value, Some(Token::Unit(u)) if u == "d") => (variable(last), 2),
+        _ => (None, 0),
+    };
+    let Some(x) = x else {
+        return Err(EvalError::Unsupported(
+            format!("Can not find the variable of {}, such as \\di{{x}}", body.to_latex()), body.span,
+        ));
+    };
+
+    let factors = &factors[..factors.len() - count];
+    let integrand = match factors {
+        // \int_0^1 \di{x} is the length of the interval
+        [] => num("1", body.span),
+        _ => product(factors),
+    };
+    Ok((integrand, x))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts;
+    use latex_analyzer::ast::AST;
+    use latex_analyzer::lex::Lex;
+    use math::util::approx::custom_approx;
+    use crate::error::EvalError;
+    use crate::integral::{integrate, split_differential};
+
+    #[test]
+    fn integrate_test() {
+        let integral = integrate(|x| Ok(x * x), 1.0, 2.0, 1e-10).unwrap();
+        assert_eq!(custom_approx(integral.value, 8).unwrap(), 2.33333333);
+        assert!(integral.error <= 1e-10);
+
+        let integral = integrate(|x| Ok(x.sin()), 0.0, consts::PI, 1e-10).unwrap();
+        assert_eq!(custom_approx(integral.value, 8).unwrap(), 2.0);
+        // agrees with Simpson's rule on the first points, but is not zero
+        let integral = integrate(|x| Ok(x.sin().powi(2)), 0.0, 2.0 * consts::PI, 1e-10).unwrap();
+        assert_eq!(custom_approx(integral.value, 8).unwrap(), custom_approx(consts::PI, 8).unwrap());
+        assert_eq!(integrate(Ok, 1.0, 0.0, 1e-10).unwrap().value, -0.5);
+
+        assert!(matches!(integrate(Ok, 0.0, f64::INFINITY, 1e-10), Err(EvalError::DomainError(..))));
+        assert!(matches!(integrate(|x| Ok(1.0 / x), -1.0, 1.0, 1e-10), Err(EvalError::DomainError(..))));
+        // singular at 0 even though it is given a value there
+        let singular = |x: f64| Ok(if x == 0.0 { 0.0 } else { 1.0 / x.abs().sqrt() });
+        assert!(matches!(integrate(singular, -1.0, 1.0, 1e-10), Err(EvalError::DomainError(..))));
+        assert!(matches!(integrate(|x| Ok((1.0 / x).sin()), 1e-4, 1.0, 1e-10), Err(EvalError::DomainError(..))));
+    }
+
+    #[test]
+    fn split_differential_test() {
+        let split = |s: &str| {
+            let AST(node, _) = AST::new(Lex::new(s.to_string()).parse().unwrap()).unwrap();
+            split_differential(&node.required_args[0]).map(|(node, x)| (node.to_latex(), x))
+        };
+
+        assert_eq!(split(r"\int_0^1 x^2 \di{x}").unwrap(), ("x^{2}".to_string(), "x".to_string()));
+        assert_eq!(split(r"\int_0^1 a t \mathrm{d}t").unwrap(), (r"a \cdot t".to_string(), "t".to_string()));
+        assert_eq!(split(r"\int_0^1 \di{x}").unwrap(), ("1".to_string(), "x".to_string()));
+        assert!(matches!(split(r"\int_0^1 x"), Err(EvalError::Unsupported(..))));
+        assert!(matches!(split(r"\int_0^1 x \di{2}"), Err(EvalError::Unsupported(..))));
+    }
+}
@@ -1,9 +1,16 @@
-mod function;
-mod exec;
+pub mod function;
+pub mod exec;
+pub mod compile;
+mod derivative;
+mod definition;
+mod simplify;
 mod buildin_function;
 mod transformer;
-mod config;
-mod known;
+pub mod config;
+pub mod error;
+pub mod unit;
+pub mod integral;
+pub mod known;
 
 fn get_config_path() -> String {
     todo!()
@@ -0,0 +1,364 @@
+//! A rewrite pass over [Node], which folds constant subtrees, removes identities such as "a + 0" and "1 \cdot x",
+//! combines like terms and collects powers of the same base.
+//! The result is calculated to the same value wherever the input is defined. Where it is not, these rewrites
+//! give a value anyway:
+//! - "0 \cdot x" is 0 even if x is not defined
+//! - "x - x" is 0 even if x is not defined
+//! - powers which cancel, such as "x \cdot x^{-1}", are 1 even if x is 0
+//!
+//! A quotient is never cancelled, so "\frac{x}{x}" stays undefined at x = 0
+use std::rc::Rc;
+use latex_analyzer::ast::{Node, NodeKind};
+use latex_analyzer::lex::Token;
+use latex_analyzer::span::Span;
+use crate::derivative::{flatten_product, num, op, superscript, unary};
+use crate::transformer::string_to_known;
+
+pub fn simplify(node: &Node) -> Node {
+    let mut node = node.clone();
+    node.left = node.left.map(|l| Rc::new(simplify(&l)));
+    node.right = node.right.map(|r| Rc::new(simplify(&r)));
+    node.optional_args = node.optional_args.iter().map(simplify).collect();
+    node.required_args = node.required_args.iter().map(simplify).collect();
+
+    match node.node_kind {
+        NodeKind::Num => simplify_function(node),
+        NodeKind::Unary => simplify_unary(node),
+        NodeKind::Equation => node,
+        NodeKind::Op => match node.op {
+            Some(Token::Add) | Some(Token::Sub) => simplify_sum(&node),
+            Some(Token::Times) => simplify_product(&node),
+            Some(Token::Div) => simplify_quotient(left(&node), right(&node), node.span),
+            Some(Token::Superscript(_)) => simplify_power(node),
+            _ => node,
+        },
+    }
+}
+
+fn simplify_function(node: Node) -> Node {
+    let Some(Token::Function(fun, _, _)) = &node.value else {
+        return node;
+    };
+
+    match (fun.as_str(), node.optional_args.as_slice(), node.required_args.as_slice()) {
+        ("frac", [], [u, v]) => simplify_quotient(u, v, node.span),
+        // only fold a root if it is exact, such as \sqrt{4}
+        ("sqrt", op, [u]) => {
+            let n = match op {
+                [] => Some(2.0),
+                [n] => value_of(n),
+                _ => None,
+            };
+            match (n, value_of(u)) {
+                (Some(n), Some(u)) if n.fract() == 0.0 && n > 0.0 && u >= 0.0 => {
+                    let root = u.powf(1.0 / n).round();
+                    if root.powi(n as i32) == u { number(root, node.span) } else { node }
+                }
+                _ => node,
+            }
+        }
+        _ => node,
+    }
+}
+
+fn simplify_unary(node: Node) -> Node {
+    let operand = right(&node);
+
+    match node.op {
+        Some(Token::Add) => operand.clone(),
+        Some(Token::Sub) => match (value_of(operand), operand.node_kind == NodeKind::Unary) {
+            (Some(v), _) => number(-v, node.span),
+            // -(-x) = x
+            (None, true) if operand.op == Some(Token::Sub) => right(operand).clone(),
+            _ => node,
+        },
+        _ => node,
+    }
+}
+
+/// Combine like terms, such as "2x + y - x + 1 + 2" is "x + y + 3".
+/// The terms keep the order they first appear, and the constant goes last
+fn simplify_sum(node: &Node) -> Node {
+    let mut terms: Vec<(String, f64, Node)> = Vec::new();
+    let mut constant = 0.0;
+
+    let mut flat = Vec::new();
+    flatten_sum(node, 1.0, &mut flat);
+    for (sign, term) in flat {
+        let (coefficient, term) = split_coefficient(term);
+        match term {
+            None => constant += sign * coefficient,
+            Some(term) => {
+                let key = term.to_latex();
+                match terms.iter_mut().find(|(k, _, _)| *k == key) {
+                    Some((_, c, _)) => *c += sign * coefficient,
+                    None => terms.push((key, sign * coefficient, term)),
+                }
+            }
+        }
+    }
+
+    let span = node.span;
+    let mut result: Option<Node> = None;
+    // "x - 2y" rather than "x + (-2y)"
+    let mut push = |coefficient: f64, term: Option<Node>| {
+        result = Some(match result.take() {
+            None => scale(coefficient, term, span),
+            Some(r) if coefficient < 0.0 => op(Token::Sub, r, scale(-coefficient, term, span), span),
+            Some(r) => op(Token::Add, r, scale(coefficient, term, span), span),
+        });
+    };
+
+    for (_, coefficient, term) in terms {
+        if coefficient != 0.0 {
+            push(coefficient, Some(term));
+        }
+    }
+    if constant != 0.0 {
+        push(constant, None);
+    }
+
+    result.unwrap_or_else(|| number(0.0, span))
+}
+
+/// Multiply numbers together and collect powers of the same base, such as "2x \cdot 3x^2" is "6x^{3}"
+fn simplify_product(node: &Node) -> Node {
+    let mut factors = Vec::new();
+    flatten_product(node, &mut factors);
+    // \mathrm{d}x at the end is the differential of an integral, such as \int_0^1 x \mathrm{d}x,
+    // so x is not a factor to collect with the others
+    let differential = match factors.as_slice() {
+        [.., d, _] if matches!(&d.value, Some(Token::Unit(u)) if u == "d") => factors.split_off(factors.len() - 2),
+        _ => vec![],
+    };
+
+    let mut coefficient = 1.0;
+    let mut powers: Vec<(String, Node, f64)> = Vec::new();
+    for factor in factors {
+        let (c, factor) = split_coefficient(factor.clone());
+        coefficient *= c;
+        let Some(factor) = factor else {
+            continue;
+        };
+
+        let (base, exponent) = match (&factor.op, factor.right.as_deref().and_then(value_of)) {
+            (Some(Token::Superscript(_)), Some(e)) => (left(&factor).clone(), e),
+            _ => (factor, 1.0),
+        };
+        let key = base.to_latex();
+        match powers.iter_mut().find(|(k, _, _)| *k == key) {
+            Some((_, _, e)) => *e += exponent,
+            None => powers.push((key, base, exponent)),
+        }
+    }
+
+    let span = node.span;
+    let product = match coefficient {
+        0.0 => number(0.0, span),
+        _ => {
+            let product = powers.into_iter()
+                .filter(|(_, _, e)| *e != 0.0)
+                .map(|(_, base, e)| if e == 1.0 { base } else { superscript(base, number(e, span), span) })
+                .reduce(|acc, f| op(Token::Times, acc, f, span));
+            scale(coefficient, product, span)
+        }
+    };
+    differential.into_iter().fold(product, |acc, f| op(Token::Times, acc, f.clone(), span))
+}
+
+/// The coefficient times term, where the coefficient is the first factor, such as "-2a \cdot b"
+fn scale(coefficient: f64, term: Option<Node>, span: Span) -> Node {
+    let Some(term) = term else {
+        return number(coefficient, span);
+    };
+
+    match coefficient {
+        1.0 => term,
+        -1.0 => unary(Token::Sub, term, span),
+        c => {
+            let mut factors = Vec::new();
+            flatten_product(&term, &mut factors);
+            factors.into_iter()
+                .fold(number(c, span), |acc, f| op(Token::Times, acc, f.clone(), span))
+        }
+    }
+}
+
+/// Fold "\frac{2}{4}" into "\frac{1}{2}", and remove "\frac{x}{1}"
+fn simplify_quotient(u: &Node, v: &Node, span: Span) -> Node {
+    match (value_of(u), value_of(v)) {
+        // leave the division by zero to evaluation, which tells where it is
+        (_, Some(0.0)) => op(Token::Div, u.clone(), v.clone(), span),
+        (Some(p), Some(q)) if p.fract() == 0.0 && q.fract() == 0.0 => {
+            let g = gcd(p.abs(), q.abs()) * q.signum();
+            let (p, q) = (p / g, q / g);
+            match q {
+                1.0 => number(p, span),
+                q => {
+                    let fraction = op(Token::Div, number(p.abs(), span), number(q, span), span);
+                    if p < 0.0 { unary(Token::Sub, fraction, span) } else { fraction }
+                }
+            }
+        }
+        (Some(p), Some(q)) => number(p / q, span),
+        (_, Some(1.0)) => u.clone(),
+        _ => op(Token::Div, u.clone(), v.clone(), span),
+    }
+}
+
+fn simplify_power(node: Node) -> Node {
+    let base = left(&node);
+    let exponent = right(&node);
+
+    match (value_of(base), value_of(exponent)) {
+        (_, Some(0.0)) => number(1.0, node.span),
+        (_, Some(1.0)) => base.clone(),
+        (Some(1.0), _) => number(1.0, node.span),
+        // an integer power of a number is exact, a negative one is a fraction
+        (Some(b), Some(e)) if e.fract() == 0.0 && b.fract() == 0.0 && b.powf(e.abs()).abs() < 1e15 => {
+            let power = b.powi(e.abs() as i32);
+            if e > 0.0 {
+                number(power, node.span)
+            } else {
+                simplify_quotient(&number(1.0, node.span), &number(power, node.span), node.span)
+            }
+        }
+        // (x^a)^b = x^{ab}, which is only true for any x if both are integers
+        (None, Some(e)) if e.fract() == 0.0 && base.op.as_ref().is_some_and(|o| matches!(o, Token::Superscript(_))) => {
+            match value_of(right(base)) {
+                Some(inner) if inner.fract() == 0.0 => {
+                    simplify_power(superscript(left(base).clone(), number(inner * e, node.span), node.span))
+                }
+                _ => node,
+            }
+        }
+        _ => node,
+    }
+}
+
+/// "a - (b - c)" gives [(1, a), (-1, b), (1, c)]
+fn flatten_sum(node: &Node, sign: f64, terms: &mut Vec<(f64, Node)>) {
+    match (&node.node_kind, &node.op) {
+        (NodeKind::Op, Some(Token::Add)) => {
+            flatten_sum(left(node), sign, terms);
+            flatten_sum(right(node), sign, terms);
+        }
+        (NodeKind::Op, Some(Token::Sub)) => {
+            flatten_sum(left(node), sign, terms);
+            flatten_sum(right(node), -sign, terms);
+        }
+        _ => terms.push((sign, node.clone())),
+    }
+}
+
+/// "-3x" gives (-3, Some(x)), a number gives (number, None)
+fn split_coefficient(node: Node) -> (f64, Option<Node>) {
+    if let Some(v) = value_of(&node) {
+        return (v, None);
+    }
+
+    match (&node.node_kind, &node.op) {
+        (NodeKind::Unary, Some(Token::Sub)) => {
+            let (c, term) = split_coefficient(right(&node).clone());
+            (-c, term)
+        }
+        (NodeKind::Op, Some(Token::Times)) => {
+            let mut factors = Vec::new();
+            flatten_product(&node, &mut factors);
+            match value_of(factors[0]) {
+                Some(c) => {
+                    let rest = factors[1..].iter()
+                        .map(|f| (*f).clone())
+                        .reduce(|acc, f| op(Token::Times, acc, f, node.span))
+                        .unwrap();
+                    (c, Some(rest))
+                }
+                None => (1.0, Some(node)),
+            }
+        }
+        _ => (1.0, Some(node)),
+    }
+}
+
+/// The value of a number, such as "2" or "-2"
+fn value_of(node: &Node) -> Option<f64> {
+    match (&node.node_kind, &node.value, &node.op) {
+        (NodeKind::Num, Some(Token::Expression(e)), _) => string_to_known(e).ok().map(|k| k.get_value()),
+        (NodeKind::Unary, _, Some(Token::Sub)) => value_of(right(node)).map(|v| -v),
+        _ => None,
+    }
+}
+
+/// A negative number is a unary "-" before the number, just as the parser does
+fn number(value: f64, span: Span) -> Node {
+    let literal = num(&format!("{}", value.abs()), span);
+    if value < 0.0 { unary(Token::Sub, literal, span) } else { literal }
+}
+
+fn gcd(a: f64, b: f64) -> f64 {
+    if b == 0.0 { a } else { gcd(b, a % b) }
+}
+
+fn left(node: &Node) -> &Node {
+    node.left.as_ref().unwrap()
+}
+
+fn right(node: &Node) -> &Node {
+    node.right.as_ref().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use latex_analyzer::ast::AST;
+    use latex_analyzer::lex::Lex;
+    use crate::simplify::simplify;
+
+    fn simplified(s: &str) -> String {
+        simplify(&AST::new(Lex::new(s.to_string()).parse().unwrap()).unwrap().0).to_latex()
+    }
+
+    #[test]
+    fn fold_test() {
+        assert_eq!(simplified("3 + 4"), "7");
+        assert_eq!(simplified(r"\frac{2}{4}"), r"\frac{1}{2}");
+        assert_eq!(simplified(r"\frac{-6}{3} + 2^3"), "6");
+        assert_eq!(simplified(r"2^{-2}"), r"\frac{1}{4}");
+        assert_eq!(simplified(r"\sqrt{16} + \sqrt[3]{27} + \sqrt{2}"), r"\sqrt{2} + 7");
+        assert_eq!(simplified("0.5 * 3"), "1.5");
+        assert_eq!(simplified("1/0"), r"\frac{1}{0}");
+    }
+
+    #[test]
+    fn identity_test() {
+        assert_eq!(simplified("a + 0"), "a");
+        assert_eq!(simplified(r"1 \cdot x"), "x");
+        assert_eq!(simplified("0x + y"), "y");
+        assert_eq!(simplified("x^1 + y^0"), "x + 1");
+        assert_eq!(simplified("x / 1 - 0"), "x");
+        assert_eq!(simplified("--x"), "x");
+        assert_eq!(simplified("0 - x"), "-x");
+        // undefined at x = 0, so left alone
+        assert_eq!(simplified(r"\frac{x}{x}"), r"\frac{x}{x}");
+        assert_eq!(simplified(r"\frac{0}{x}"), r"\frac{0}{x}");
+    }
+
+    #[test]
+    fn like_terms_test() {
+        assert_eq!(simplified("2x + y - x + 1 + 2"), "x + y + 3");
+        assert_eq!(simplified("x - x"), "0");
+        assert_eq!(simplified("a b - 3 a b"), r"-2a \cdot b");
+        assert_eq!(simplified(r"\frac{x}{y} + \frac{x}{y}"), r"2\frac{x}{y}");
+    }
+
+    #[test]
+    fn power_test() {
+        assert_eq!(simplified(r"2x \cdot 3x^2"), "6x^{3}");
+        assert_eq!(simplified(r"x \cdot y \cdot x"), r"x^{2} \cdot y");
+        assert_eq!(simplified(r"x^2 \cdot x^{-2}"), "1");
+        assert_eq!(simplified(r"x \cdot x^{-1}"), "1");
+        assert_eq!(simplified("{x^2}^3"), "x^{6}");
+        assert_eq!(simplified(r"{x^2}^{\frac{1}{2}}"), r"(x^{2})^{\frac{1}{2}}");
+        // the differential of an integral is not a factor
+        assert_eq!(simplified(r"\int_0^1 2x \cdot 3 \mathrm{d}x"), r"\int_{0}^{1} 6x \cdot \mathrm{d} \cdot x");
+    }
+}
@@ -1,26 +1,25 @@
 use lazy_static::lazy_static;
+use latex_analyzer::span::Span;
 use regex::Regex;
+use crate::error::EvalError;
 use crate::known::Known;
 
-pub fn strings_to_known(v: &Vec<String>) -> Vec<Box<dyn Known>>{
-    v.iter().map(|s| string_to_known(s).unwrap()).collect()
-}
-
-pub fn string_to_known(s: &String) -> Option<Box<dyn Known>>  {
+pub fn string_to_known(s: &str) -> Result<Box<dyn Known>, EvalError>  {
     lazy_static! {
         // match numbers, such as 1 or 1.1
-        static ref PURE_NUMBER: Regex = Regex::new(r"-?\d+(\.\d+)?").unwrap();
+        static ref PURE_NUMBER: Regex = Regex::new(r"^-?\d+(\.\d+)?$").unwrap();
     }
 
     if PURE_NUMBER.is_match(s) {
-        return Some(Box::new(s.parse::<f64>().unwrap()));
+        return Ok(Box::new(s.parse::<f64>().unwrap()));
     }
 
-    None
+    Err(EvalError::InvalidNumber(s.to_string(), Span::default()))
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::error::EvalError;
     use crate::transformer::string_to_known;
 
     #[test]
@@ -40,5 +39,7 @@ mod tests {
         let s4 = "01.2".to_string();
         let r4 = string_to_known(&s4).unwrap().get_value();
         assert_eq!(r4, 1.2);
+
+        assert!(matches!(string_to_known("T_0"), Err(EvalError::InvalidNumber(_, _))));
     }
 }
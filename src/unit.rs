@@ -0,0 +1,357 @@
+//! [crate::unit] gives values physical units, such as the "\mathrm{m/s}" in 3\,\mathrm{m/s}.
+//! A value is stored in SI base units together with its dimension,
+//! so 1\,\mathrm{km} and 1000\,\mathrm{m} are the same quantity, but 1\,\mathrm{m} + 1\,\mathrm{s} is an error
+//!
+use std::fmt::{Display, Formatter};
+use lazy_static::lazy_static;
+use latex_analyzer::span::Span;
+use crate::compile::pow;
+use crate::error::EvalError;
+
+// SI base units, the dimension of a quantity is how many times each of them appears
+const BASE_UNITS: [&str; 7] = ["kg", "m", "s", "A", "K", "mol", "cd"];
+
+/// Exponents of SI base units, in the order of kg, m, s, A, K, mol and cd
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Dimension([i8; 7]);
+
+impl Dimension {
+    pub fn is_dimensionless(&self) -> bool {
+        self.0 == [0; 7]
+    }
+
+    pub(crate) fn times(self, other: Dimension) -> Result<Dimension, EvalError> {
+        self.combine(other, i8::checked_add)
+    }
+
+    fn div(self, other: Dimension) -> Result<Dimension, EvalError> {
+        self.combine(other, i8::checked_sub)
+    }
+
+    /// An error if some exponent is not an integer, such as the square root of m
+    fn pow(self, exponent: f64) -> Result<Dimension, EvalError> {
+        let mut result = [0; 7];
+        for (i, r) in result.iter_mut().enumerate() {
+            let x = self.0[i] as f64 * exponent;
+            if !x.is_finite() || (x - x.round()).abs() > 1e-9 {
+                return Err(EvalError::DimensionError(format!("Can not raise {self} to {exponent}"), Span::default()));
+            }
+            *r = i8::try_from(x.round() as i64).map_err(|_| Dimension::out_of_range(i))?;
+        }
+
+        Ok(Dimension(result))
+    }
+
+    fn combine(self, other: Dimension, f: fn(i8, i8) -> Option<i8>) -> Result<Dimension, EvalError> {
+        let mut result = [0; 7];
+        for (i, r) in result.iter_mut().enumerate() {
+            *r = f(self.0[i], other.0[i]).ok_or_else(|| Dimension::out_of_range(i))?;
+        }
+
+        Ok(Dimension(result))
+    }
+
+    // exponents are stored in i8, such as m^{200} is too much
+    fn out_of_range(i: usize) -> EvalError {
+        EvalError::DimensionError(format!("The exponent of {} is out of range", BASE_UNITS[i]), Span::default())
+    }
+}
+
+impl Display for Dimension {
+    /// Such as "kg m^2/s^2", and "1/s" if there are only units in the denominator
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let units = |positive: bool| -> Vec<String> {
+            BASE_UNITS.iter().zip(self.0)
+                .filter(|(_, e)| if positive { *e > 0 } else { *e < 0 })
+                .map(|(unit, e)| match e.abs() {
+                    1 => unit.to_string(),
+                    e if e < 10 => format!("{unit}^{e}"),
+                    e => format!("{unit}^{{{e}}}"),
+                })
+                .collect()
+        };
+        let (numerator, denominator) = (units(true), units(false));
+
+        let numerator = if numerator.is_empty() { "1".to_string() } else { numerator.join(" ") };
+        if denominator.is_empty() {
+            write!(f, "{numerator}")
+        } else {
+            write!(f, "{numerator}/{}", denominator.join(" "))
+        }
+    }
+}
+
+/// A value in SI base units with its dimension
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct Quantity {
+    pub value: f64,
+    pub dimension: Dimension,
+}
+
+impl Quantity {
+    /// value in unit, such as `Quantity::new(3.6, "km/h")` is 1 m/s
+    pub fn new(value: f64, unit: &str) -> Result<Quantity, EvalError> {
+        Quantity::number(value).times(Quantity::unit(unit)?)
+    }
+
+    pub fn number(value: f64) -> Quantity {
+        Quantity { value, dimension: Dimension::default() }
+    }
+
+    /// The value in unit, such as 1 m/s is 3.6 km/h
+    pub fn to(&self, unit: &str) -> Result<f64, EvalError> {
+        let unit = Quantity::unit(unit)?;
+        if unit.dimension != self.dimension {
+            return Err(EvalError::DimensionError(
+                format!("Can not convert {} to {}", self.dimension, unit.dimension), Span::default(),
+            ));
+        }
+
+        Ok(self.value / unit.value)
+    }
+
+    /// 1 unit, such as "km/h" is 1/3.6 m/s.
+    /// Units in a product are separated by spaces or \cdot, such as "kg m^2/s^2" and "N \cdot m",
+    /// and everything after "/" is in the denominator
+    pub fn unit(unit: &str) -> Result<Quantity, EvalError> {
+        let unknown = || EvalError::UnknownUnit(unit.to_string(), Span::default());
+
+        let normalized = unit.replace(r"\cdot", " ").replace(r"\,", " ")
+            .replace(r"\mu ", "µ").replace(r"\mu", "µ")
+            .replace(['(', ')', '*'], " ");
+        // such as "" or "/", which has no unit at all
+        if normalized.split('/').all(|part| part.trim().is_empty()) {
+            return Err(unknown());
+        }
+
+        let mut result = Quantity::number(1.0);
+        for (i, part) in normalized.split('/').enumerate() {
+            // "1" is only a placeholder, such as "1/s"
+            for factor in part.split_whitespace().filter(|f| *f != "1") {
+                let (symbol, exponent) = match factor.split_once('^') {
+                    Some((symbol, exponent)) => {
+                        let exponent = exponent.trim_matches(|c| c == '{' || c == '}');
+                        (symbol, exponent.parse::<i8>().map_err(|_| unknown())?)
+                    }
+                    None => (factor, 1),
+                };
+                let exponent = if i == 0 { exponent } else { -exponent };
+                let base = symbol_to_quantity(symbol).ok_or_else(unknown)?;
+                result = result.times(base.pow(exponent as f64)?)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub(crate) fn add(self, other: Quantity) -> Result<Quantity, EvalError> {
+        self.same_dimension(other, "add")?;
+        Ok(Quantity { value: self.value + other.value, dimension: self.dimension })
+    }
+
+    pub(crate) fn sub(self, other: Quantity) -> Result<Quantity, EvalError> {
+        self.same_dimension(other, "subtract")?;
+        Ok(Quantity { value: self.value - other.value, dimension: self.dimension })
+    }
+
+    pub(crate) fn times(self, other: Quantity) -> Result<Quantity, EvalError> {
+        Ok(Quantity { value: self.value * other.value, dimension: self.dimension.times(other.dimension)? })
+    }
+
+    pub(crate) fn div(self, other: Quantity) -> Result<Quantity, EvalError> {
+        Ok(Quantity { value: self.value / other.value, dimension: self.dimension.div(other.dimension)? })
+    }
+
+    pub(crate) fn neg(self) -> Quantity {
+        Quantity { value: -self.value, ..self }
+    }
+
+    /// An error if the dimension can not be raised to exponent, such as m^{0.5}
+    pub(crate) fn pow(self, exponent: f64) -> Result<Quantity, EvalError> {
+        Ok(Quantity { value: pow(self.value, exponent), dimension: self.dimension.pow(exponent)? })
+    }
+
+    /// Check the dimension before comparing or adding
+    pub(crate) fn same_dimension(&self, other: Quantity, action: &str) -> Result<(), EvalError> {
+        if self.dimension != other.dimension {
+            return Err(EvalError::DimensionError(
+                format!("Can not {action} {} and {}", self.dimension_name(), other.dimension_name()),
+                Span::default(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn dimension_name(&self) -> String {
+        if self.dimension.is_dimensionless() {
+            "a number without unit".to_string()
+        } else {
+            self.dimension.to_string()
+        }
+    }
+}
+
+impl Display for Quantity {
+    /// Such as "3\,\mathrm{m/s}", or just "3" for a number without unit
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.dimension.is_dimensionless() {
+            write!(f, "{}", self.value)
+        } else {
+            write!(f, "{}\\,\\mathrm{{{}}}", self.value, self.dimension)
+        }
+    }
+}
+
+/// The dimension of the result of a built-in function, such as \sqrt{x} where x is in m^2 gives m.
+/// Functions which are not known here, such as \ln, only take numbers without unit
+pub(crate) fn function_dimension(name: &str, optional: &[Quantity], required: &[Quantity])
+                                 -> Result<Dimension, EvalError> {
+    let error = |message: String| Err(EvalError::DimensionError(message, Span::default()));
+
+    match (name, optional, required) {
+        ("frac", _, [a, b, ..]) => a.dimension.div(b.dimension),
+        ("sqrt", _, [x, ..]) => {
+            let n = optional.first().map_or(2.0, |n| n.value);
+            match x.dimension.pow(1.0 / n) {
+                Ok(dimension) => Ok(dimension),
+                Err(_) => error(format!("Can not take the {n}th root of {}", x.dimension)),
+            }
+        }
+        _ if optional.iter().chain(required).all(|q| q.dimension.is_dimensionless()) => Ok(Dimension::default()),
+        _ => error(format!("The arguments of \\{name} can not have units")),
+    }
+}
+
+struct UnitSymbol {
+    symbol: &'static str,
+    scale: f64,
+    dimension: Dimension,
+    // hours and minutes do not take prefixes, and "h" in "hPa" is hecto
+    prefixable: bool,
+}
+
+lazy_static! {
+    static ref UNIT_SYMBOL: Vec<UnitSymbol> = {
+        let unit = |symbol, scale, dimension, prefixable| UnitSymbol { symbol, scale, dimension: Dimension(dimension), prefixable };
+        vec![
+            unit("g", 1e-3, [1, 0, 0, 0, 0, 0, 0], true),
+            unit("m", 1.0, [0, 1, 0, 0, 0, 0, 0], true),
+            unit("s", 1.0, [0, 0, 1, 0, 0, 0, 0], true),
+            unit("A", 1.0, [0, 0, 0, 1, 0, 0, 0], true),
+            unit("K", 1.0, [0, 0, 0, 0, 1, 0, 0], true),
+            unit("mol", 1.0, [0, 0, 0, 0, 0, 1, 0], true),
+            unit("cd", 1.0, [0, 0, 0, 0, 0, 0, 1], true),
+            unit("Hz", 1.0, [0, 0, -1, 0, 0, 0, 0], true),
+            unit("N", 1.0, [1, 1, -2, 0, 0, 0, 0], true),
+            unit("Pa", 1.0, [1, -1, -2, 0, 0, 0, 0], true),
+            unit("J", 1.0, [1, 2, -2, 0, 0, 0, 0], true),
+            unit("W", 1.0, [1, 2, -3, 0, 0, 0, 0], true),
+            unit("C", 1.0, [0, 0, 1, 1, 0, 0, 0], true),
+            unit("V", 1.0, [1, 2, -3, -1, 0, 0, 0], true),
+            unit("\\Omega", 1.0, [1, 2, -3, -2, 0, 0, 0], true),
+            unit("F", 1.0, [-1, -2, 4, 2, 0, 0, 0], true),
+            unit("S", 1.0, [-1, -2, 3, 2, 0, 0, 0], true),
+            unit("T", 1.0, [1, 0, -2, -1, 0, 0, 0], true),
+            unit("Wb", 1.0, [1, 2, -2, -1, 0, 0, 0], true),
+            unit("H", 1.0, [1, 2, -2, -2, 0, 0, 0], true),
+            unit("L", 1e-3, [0, 3, 0, 0, 0, 0, 0], true),
+            unit("eV", 1.602176634e-19, [1, 2, -2, 0, 0, 0, 0], true),
+            unit("min", 60.0, [0, 0, 1, 0, 0, 0, 0], false),
+            unit("h", 3600.0, [0, 0, 1, 0, 0, 0, 0], false),
+            unit("d", 86400.0, [0, 0, 1, 0, 0, 0, 0], false),
+            unit("t", 1e3, [1, 0, 0, 0, 0, 0, 0], false),
+            unit("bar", 1e5, [1, -1, -2, 0, 0, 0, 0], false),
+        ]
+    };
+
+    // "da" goes before "d", so "dam" is decametre rather than deci-am
+    static ref PREFIX: Vec<(&'static str, f64)> = vec![
+        ("Y", 1e24), ("Z", 1e21), ("E", 1e18), ("P", 1e15), ("T", 1e12), ("G", 1e9), ("M", 1e6),
+        ("k", 1e3), ("h", 1e2), ("da", 1e1), ("d", 1e-1), ("c", 1e-2), ("m", 1e-3),
+        ("µ", 1e-6), ("u", 1e-6), ("n", 1e-9), ("p", 1e-12), ("f", 1e-15), ("a", 1e-18),
+    ];
+}
+
+/// A unit symbol with an optional prefix, a symbol which is a unit itself is not split,
+/// so "m" is metre and "T" is tesla, but "mm" is millimetre
+fn symbol_to_quantity(symbol: &str) -> Option<Quantity> {
+    let quantity = |u: &UnitSymbol, scale: f64| Quantity { value: u.scale * scale, dimension: u.dimension };
+
+    if let Some(u) = UNIT_SYMBOL.iter().find(|u| u.symbol == symbol) {
+        return Some(quantity(u, 1.0));
+    }
+    PREFIX.iter().find_map(|(prefix, scale)| {
+        let rest = symbol.strip_prefix(prefix)?;
+        let u = UNIT_SYMBOL.iter().find(|u| u.symbol == rest && u.prefixable)?;
+        Some(quantity(u, *scale))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use latex_analyzer::lex::UNIT_SYMBOL;
+    use math::util::approx::custom_approx;
+    use crate::error::EvalError;
+    use crate::unit::{function_dimension, Quantity};
+
+    #[test]
+    fn unit_test() {
+        let unit = |s: &str| Quantity::unit(s).unwrap();
+
+        assert_eq!(unit("kg").value, 1.0);
+        assert_eq!(unit("mm").value, 1e-3);
+        assert_eq!(unit("km/h").value, 1.0 / 3.6);
+        assert_eq!(unit("N").dimension, unit("kg m/s^2").dimension);
+        assert_eq!(unit("J").dimension, unit(r"N \cdot m").dimension);
+        assert_eq!(unit("W").dimension, unit("J/s").dimension);
+        assert_eq!(unit("Hz").dimension, unit("s^{-1}").dimension);
+        assert_eq!(unit(r"\mu m").value, 1e-6);
+        assert_eq!(unit("hPa").value, 100.0);
+        assert_eq!(unit("J/(kg K)").dimension, unit("m^2/s^2 K").dimension);
+
+        assert!(matches!(Quantity::unit("furlong"), Err(EvalError::UnknownUnit(..))));
+        assert!(matches!(Quantity::unit("kmin"), Err(EvalError::UnknownUnit(..))));
+        assert!(matches!(Quantity::unit(""), Err(EvalError::UnknownUnit(..))));
+        // the lexer only knows the names of units, which should be the same as ours
+        for (symbol, prefixable) in UNIT_SYMBOL.iter() {
+            assert!(Quantity::unit(symbol).is_ok());
+            assert_eq!(Quantity::unit(&format!("k{symbol}")).is_ok(), *prefixable);
+        }
+    }
+
+    #[test]
+    fn quantity_test() {
+        let speed = Quantity::new(36.0, "km/h").unwrap();
+        assert_eq!(custom_approx(speed.value, 6).unwrap(), 10.0);
+        assert_eq!(custom_approx(speed.to("m/s").unwrap(), 6).unwrap(), 10.0);
+        assert!(matches!(speed.to("s"), Err(EvalError::DimensionError(..))));
+
+        assert_eq!(Quantity::new(2.0, "kg m^2/s^2").unwrap().to_string(), r"2\,\mathrm{kg m^2/s^2}");
+        assert_eq!(Quantity::new(2.0, "Hz").unwrap().to_string(), r"2\,\mathrm{1/s}");
+        assert_eq!(Quantity::unit("1/s").unwrap(), Quantity::unit("Hz").unwrap());
+        assert_eq!(Quantity::number(2.0).to_string(), "2");
+
+        let length = Quantity::new(4.0, "m^2").unwrap();
+        assert_eq!(length.pow(0.5).unwrap(), Quantity::new(2.0, "m").unwrap());
+        assert!(Quantity::new(4.0, "m").unwrap().pow(0.5).is_err());
+        assert!(length.add(Quantity::number(1.0)).is_err());
+
+        // exponents which do not fit are errors rather than overflows
+        let metre = Quantity::unit("m").unwrap();
+        assert!(matches!(metre.pow(100.0).unwrap().times(metre.pow(100.0).unwrap()), Err(EvalError::DimensionError(..))));
+        assert!(matches!(metre.pow(300.0), Err(EvalError::DimensionError(..))));
+        assert!(matches!(metre.pow(-100.0).unwrap().div(metre.pow(100.0).unwrap()), Err(EvalError::DimensionError(..))));
+    }
+
+    #[test]
+    fn function_dimension_test() {
+        let m2 = Quantity::new(4.0, "m^2").unwrap();
+        let s = Quantity::new(2.0, "s").unwrap();
+
+        assert_eq!(function_dimension("sqrt", &[], &[m2]).unwrap(), Quantity::unit("m").unwrap().dimension);
+        assert_eq!(function_dimension("frac", &[], &[m2, s]).unwrap(), Quantity::unit("m^2/s").unwrap().dimension);
+        assert!(function_dimension("sqrt", &[Quantity::number(3.0)], &[m2]).is_err());
+        assert!(function_dimension("ln", &[], &[s]).is_err());
+        assert!(function_dimension("ln", &[], &[Quantity::number(2.0)]).unwrap().is_dimensionless());
+    }
+}